@@ -0,0 +1,398 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    cli::{Backend, Config},
+    metadata::Package,
+    public,
+    tags::{Kind, Tag, Visibility},
+    write_atomic,
+};
+
+/// First line of every cache file, bumped whenever the layout changes.
+const HEADER: &str = "cargo-symbols cache 3";
+
+/// 64-bit FNV-1a hash, stable across runs and platforms unlike the hasher of
+/// the standard library.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Directory of the cache: `$XDG_CACHE_HOME/cargo-symbols`, or
+/// `$CARGO_HOME/cargo-symbols` when that is not set.
+fn cache_dir() -> Option<PathBuf> {
+    if let Some(cache) = env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(cache).join("cargo-symbols"));
+    }
+
+    let cargo_home = match env::var_os("CARGO_HOME").filter(|dir| !dir.is_empty()) {
+        Some(cargo_home) => PathBuf::from(cargo_home),
+        None => PathBuf::from(env::var_os("HOME")?).join(".cargo"),
+    };
+    Some(cargo_home.join("cargo-symbols"))
+}
+
+/// Checksums of the registry packages in `Cargo.lock`, by name, version and
+/// source.
+fn lock_checksums(lock: &str) -> HashMap<(String, String, String), String> {
+    fn field(line: &str, key: &str) -> Option<String> {
+        let value = line.strip_prefix(key)?.trim_start().strip_prefix('=')?;
+        Some(value.trim().trim_matches('"').to_owned())
+    }
+
+    let mut checksums = HashMap::new();
+    for package in lock.split("[[package]]").skip(1) {
+        let (mut name, mut version, mut source, mut checksum) = (None, None, None, None);
+        for line in package.lines() {
+            name = name.or_else(|| field(line, "name"));
+            version = version.or_else(|| field(line, "version"));
+            source = source.or_else(|| field(line, "source"));
+            checksum = checksum.or_else(|| field(line, "checksum"));
+        }
+        if let (Some(name), Some(version), Some(source), Some(checksum)) =
+            (name, version, source, checksum)
+        {
+            checksums.insert((name, version, source), checksum);
+        }
+    }
+
+    checksums
+}
+
+/// Modification time of `file` in nanoseconds, `-` when it does not exist.
+pub fn mtime(file: &Path) -> String {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(|| "-".to_owned(), |since| since.as_nanos().to_string())
+}
+
+pub fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+pub fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => break,
+        }
+    }
+    unescaped
+}
+
+/// Tags of packages from earlier runs, one file per package.
+///
+/// A cache file is found by the package id, the checksum of its source and
+/// everything else that changes its tags. Registry sources are immutable and
+/// git ids carry the commit, so those entries stay valid as long as they are
+/// found. Path packages are edited in place, so their entries also record the
+/// modification time of every file of the module tree and are rejected when
+/// any of them changed.
+pub struct Cache {
+    dir: PathBuf,
+    checksums: HashMap<(String, String, String), String>,
+    /// Options that change the tags generated for a package.
+    options: String,
+    /// Whether `--public-only` was given, which changes the tags of the
+    /// packages it applies to.
+    public_only: bool,
+}
+
+impl Cache {
+    /// The cache of the user, or `None` when there is no place to put it.
+    pub fn open(workspace_root: &Path, config: &Config) -> Option<Self> {
+        let checksums = fs::read_to_string(workspace_root.join("Cargo.lock"))
+            .map(|lock| lock_checksums(&lock))
+            .unwrap_or_default();
+
+        let backend = match config.backend {
+            Backend::Native => "native".to_owned(),
+            Backend::Ctags => format!("ctags {} {:?}", config.ctags, config.ctags_args),
+        };
+        let options = format!(
+            "{} {backend} build-scripts={} examples={} tests={} benches={}",
+            env!("CARGO_PKG_VERSION"),
+            config.build_scripts,
+            config.examples,
+            config.tests,
+            config.benches,
+        );
+
+        Some(Self {
+            dir: cache_dir()?,
+            checksums,
+            options,
+            public_only: config.public_only,
+        })
+    }
+
+    fn key(&self, package: &Package) -> String {
+        let checksum = package.source.as_ref().and_then(|source| {
+            let key = (
+                package.name.clone(),
+                package.version.clone(),
+                source.clone(),
+            );
+            self.checksums.get(&key)
+        });
+
+        let mut key = format!(
+            "{} {} {} public-only={}",
+            package.id,
+            checksum.map_or("-", String::as_str),
+            self.options,
+            public::applies(self.public_only, package.tier),
+        );
+        for target in &package.targets {
+            let kind = target.kind.join(",");
+            key += &format!(" {kind}:{}={}", target.name, target.src_path.display());
+        }
+        key
+    }
+
+    fn path(&self, key: &str, package: &Package) -> PathBuf {
+        let name = format!(
+            "{}-{}-{:016x}",
+            package.name,
+            package.version,
+            fnv1a(key.as_bytes())
+        );
+        self.dir.join(name)
+    }
+
+    /// Cached tags of `package` and the files they come from, if still valid.
+    pub fn load(&self, package: &Package) -> Option<(Vec<Tag>, Vec<PathBuf>)> {
+        let key = self.key(package);
+        let cached = fs::read_to_string(self.path(&key, package)).ok()?;
+        let mut lines = cached.lines();
+        if lines.next() != Some(HEADER) || lines.next().map(unescape) != Some(key) {
+            return None;
+        }
+
+        let immutable = package.is_immutable();
+        let mut files = vec![];
+        let mut tags = vec![];
+        for line in lines {
+            let fields: Vec<_> = line.split('\t').collect();
+            match fields[..] {
+                ["file", modified, file] => {
+                    let file = PathBuf::from(unescape(file));
+                    if !immutable && mtime(&file) != modified {
+                        return None;
+                    }
+                    files.push(file);
+                }
+                ["tag", kind, file, line, column, end_line, offset, visibility, name, scope, pattern] => {
+                    tags.push(Tag {
+                        name: unescape(name),
+                        file: files.get(file.parse::<usize>().ok()?)?.clone(),
+                        line: line.parse().ok()?,
+                        column: column.parse().ok()?,
+                        end_line: end_line.parse().ok()?,
+                        offset: offset.parse().ok()?,
+                        kind: Kind::from_letter(kind.chars().next()?)?,
+                        pattern: unescape(pattern),
+                        scope: unescape(scope),
+                        visibility: match visibility {
+                            "-" => None,
+                            visibility => Some(Visibility::from_name(visibility)?),
+                        },
+                    })
+                }
+                _ => return None,
+            }
+        }
+
+        Some((tags, files))
+    }
+
+    /// Cache the `tags` of `package`, generated from `files`.
+    pub fn store(
+        &self,
+        package: &Package,
+        tags: &[Tag],
+        files: &HashSet<PathBuf>,
+    ) -> io::Result<()> {
+        let key = self.key(package);
+        fs::create_dir_all(&self.dir)?;
+
+        // ctags may name the files of its tags differently than asked
+        let files: BTreeSet<&PathBuf> = files
+            .iter()
+            .chain(tags.iter().map(|tag| &tag.file))
+            .collect();
+        let index: HashMap<_, _> = files
+            .iter()
+            .enumerate()
+            .map(|(i, &file)| (file, i))
+            .collect();
+
+        write_atomic(&self.path(&key, package), |w| {
+            writeln!(w, "{HEADER}")?;
+            writeln!(w, "{}", escape(&key))?;
+            for file in &files {
+                let path = escape(&file.to_string_lossy());
+                writeln!(w, "file\t{}\t{path}", mtime(file))?;
+            }
+            for tag in tags {
+                let file = index[&tag.file];
+                writeln!(
+                    w,
+                    "tag\t{}\t{file}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    tag.kind.letter(),
+                    tag.line,
+                    tag.column,
+                    tag.end_line,
+                    tag.offset,
+                    tag.visibility.map_or("-", Visibility::name),
+                    escape(&tag.name),
+                    escape(&tag.scope),
+                    escape(&tag.pattern)
+                )?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Tier;
+
+    #[test]
+    fn escapes() {
+        for field in [
+            "",
+            "plain",
+            "a\tb\nc\rd",
+            "back\\slash",
+            "\\t",
+            "ends\\",
+            "é\t中",
+        ] {
+            let escaped = escape(field);
+            assert!(!escaped.contains(['\t', '\n', '\r']), "{escaped:?}");
+            assert_eq!(unescape(&escaped), field);
+        }
+    }
+
+    /// A cache in a directory of its own, and a source file of a path package.
+    fn cache(test: &str, config: &Config) -> (Cache, PathBuf) {
+        let dir = env::temp_dir().join(format!("cargo-symbols-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.rs");
+        fs::write(&file, "pub fn f() {}\n").unwrap();
+
+        let mut cache = Cache::open(&dir, config).unwrap();
+        cache.dir = dir.join("cache");
+        (cache, file)
+    }
+
+    fn package(tier: Tier) -> Package {
+        Package {
+            id: "path+file:///proj#0.1.0".to_owned(),
+            name: "proj".to_owned(),
+            version: "0.1.0".to_owned(),
+            tier,
+            ..Package::default()
+        }
+    }
+
+    fn tag(file: &Path) -> Tag {
+        Tag {
+            name: "f".to_owned(),
+            file: file.to_owned(),
+            line: 1,
+            column: 8,
+            end_line: 1,
+            offset: 0,
+            kind: Kind::Function,
+            pattern: "pub fn f() {}\t// tab".to_owned(),
+            scope: "proj".to_owned(),
+            visibility: Some(Visibility::Public),
+        }
+    }
+
+    #[test]
+    fn store_and_load() {
+        let (cache, file) = cache("store", &Config::default());
+        let package = package(Tier::Workspace);
+        assert!(cache.load(&package).is_none());
+
+        let files = HashSet::from([file.clone()]);
+        cache.store(&package, &[tag(&file)], &files).unwrap();
+        let (tags, files) = cache.load(&package).unwrap();
+        assert_eq!(files, vec![file.clone()]);
+        assert_eq!(tags.len(), 1);
+        let expected = tag(&file);
+        assert_eq!(
+            (
+                &tags[0].name,
+                &tags[0].file,
+                tags[0].column,
+                &tags[0].pattern
+            ),
+            (
+                &expected.name,
+                &expected.file,
+                expected.column,
+                &expected.pattern
+            )
+        );
+        assert_eq!(tags[0].kind, Kind::Function);
+        assert_eq!(tags[0].visibility, Some(Visibility::Public));
+
+        // path packages are tagged again when their files change
+        fs::write(&file, "pub fn g() {}\n").unwrap();
+        let modified = fs::File::options().write(true).open(&file).unwrap();
+        modified
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert!(cache.load(&package).is_none());
+    }
+
+    #[test]
+    fn public_only_by_tier() {
+        let config = Config {
+            public_only: true,
+            ..Config::default()
+        };
+        let (cache, file) = cache("public-only", &config);
+        let files = HashSet::from([file.clone()]);
+
+        // tagged filtered as a dependency, then whole as a workspace member
+        let dependency = package(Tier::Direct);
+        cache.store(&dependency, &[], &files).unwrap();
+        let member = package(Tier::Workspace);
+        assert!(cache.load(&member).is_none());
+        cache.store(&member, &[tag(&file)], &files).unwrap();
+
+        assert_eq!(cache.load(&dependency).unwrap().0.len(), 0);
+        assert_eq!(cache.load(&member).unwrap().0.len(), 1);
+        assert_eq!(cache.load(&package(Tier::Transitive)).unwrap().0.len(), 0);
+    }
+}
@@ -0,0 +1,735 @@
+use std::path::{Path, PathBuf};
+
+use crate::{filter::PackageSpec, json::Json, metadata::Tier, tags::Format, AnyError};
+
+const USAGE: &str = "\
+Generate tags for the top-level cargo project dependencies
+
+Usage: cargo symbols [OPTIONS]
+       cargo symbols find [OPTIONS] <PATTERN>
+       cargo symbols lsp [OPTIONS]
+
+Commands:
+  find <PATTERN>              Print the symbols of the tags file matching
+                              PATTERN, generating the file when it is missing.
+                              Patterns containing `::` match qualified paths
+  lsp                         Serve the symbols of the tags file to an editor,
+                              as a language server on stdin and stdout
+
+Options:
+  -o, --output <PATH>         Write the tags to PATH [default: tags, TAGS for
+                              etags or tags.<format>, in the workspace root]
+      --split                 Write one tags file per package, with indexes for
+                              vim and Emacs, to the directory PATH [default:
+                              tags in the target directory]
+      --in-target-dir         Write the tags file to the target directory instead
+                              of the workspace root
+      --format <FORMAT>       Tags file format: ctags, etags, json, jsonl, sqlite
+                              [default: ctags]
+      --relative              Write source paths relative to the tags file
+      --remap-path-prefix <FROM=TO>
+                              Write source paths starting with FROM as starting
+                              with TO instead, the last matching one applying
+      --qualified             Also tag every item by its fully qualified path,
+                              such as serde::de::Error
+      --public-only           Only tag the public API of the dependencies, the
+                              items reachable from their crate root, keeping
+                              everything of the workspace members
+      --priority <TIERS>      Comma separated order in which the tags of a name
+                              are listed, by package: workspace, direct,
+                              transitive, std [default: in that order]
+      --append                Merge into the existing tags file instead of replacing it
+      --manifest-path <PATH>  Path to the Cargo.toml of the project
+      --backend <BACKEND>     Tag generator to use: native, ctags [default: native]
+      --ctags <PATH>          ctags executable to run [default: ctags]
+      --ctags-arg <ARG>       Extra argument to pass to ctags, repeatable
+      --sqlite3 <PATH>        sqlite3 executable to create databases with
+                              [default: sqlite3]
+  -F, --features <FEATURES>   Space or comma separated list of features to activate
+      --all-features          Activate all available features
+      --no-default-features   Do not activate the `default` feature
+      --filter-platform <TRIPLE>
+                              Only include dependencies for the target TRIPLE
+      --include <SPEC>        Only tag the packages matching SPEC, a name glob
+                              optionally prefixed by a source kind (registry,
+                              git, path) and followed by @ and a version
+                              requirement, e.g. `registry:serde*@^1`
+  -p, --package <SPEC>        Same as --include
+      --exclude <SPEC>        Do not tag the packages matching SPEC
+      --depth <N>             Only tag dependencies up to N edges away from the
+                              workspace members, 1 being direct dependencies
+      --no-dev                Do not follow dev-dependencies
+      --no-build              Do not follow build-dependencies
+      --std                   Also tag the standard library, from the rust-src
+                              component of the toolchain
+      --build-scripts         Also tag build scripts
+      --examples              Also tag examples
+      --tests                 Also tag integration tests
+      --benches               Also tag benchmarks
+                              (--std and these, as well as --split,
+                              --in-target-dir, --relative, --qualified and
+                              --public-only, have a --no-... counterpart)
+      --match <MODE>          How `find` and `lsp` match names: exact, prefix,
+                              substring, fuzzy [default: prefix]
+      --list                  Only list the packages that would be tagged, with
+                              their source roots
+      --message-format <FMT>  Format of the list: human, json [default: human]
+      --check                 Only check whether the tags file is up to date,
+                              exiting with 1 when it is not
+      --force                 Generate the tags file even when it is up to date
+  -j, --jobs <N>              Number of packages to tag in parallel
+                              [default: available parallelism]
+      --no-cache              Tag every package again instead of reusing the tags
+                              of earlier runs
+  -v, --verbose               Print every command that is run
+  -q, --quiet                 Do not print progress messages
+  -h, --help                  Print help
+  -V, --version               Print version
+
+Settings can also be checked into Cargo.toml, in the [workspace.metadata.symbols]
+and [package.metadata.symbols] tables of the root package, named after the long
+options: `depth = 1`, `std = true`, `exclude = [\"*-sys\"]`. Options given on the
+command line override them.
+";
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Backend {
+    /// The built-in Rust source scanner.
+    Native,
+    /// An external ctags executable.
+    Ctags,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MessageFormat {
+    Human,
+    /// One JSON object per line.
+    Json,
+}
+
+/// How `find` and `lsp` match the names of symbols to its pattern.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MatchMode {
+    Exact,
+    Prefix,
+    Substring,
+    /// The characters of the pattern in order, ignoring case.
+    Fuzzy,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Subcommand {
+    /// Write the tags file.
+    Tag,
+    /// Search the tags file for a pattern.
+    Find(String),
+    /// Serve the tags file as a language server.
+    Lsp,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub subcommand: Subcommand,
+    pub output: Option<PathBuf>,
+    pub in_target_dir: bool,
+    pub split: bool,
+    pub format: Format,
+    pub append: bool,
+    pub priority: Vec<Tier>,
+    pub qualified: bool,
+    pub public_only: bool,
+    pub relative: bool,
+    pub remap_path_prefix: Vec<(PathBuf, PathBuf)>,
+    pub manifest_path: Option<PathBuf>,
+    pub backend: Backend,
+    pub ctags: String,
+    pub ctags_args: Vec<String>,
+    pub sqlite3: String,
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub filter_platform: Option<String>,
+    pub include: Vec<PackageSpec>,
+    pub exclude: Vec<PackageSpec>,
+    pub depth: Option<usize>,
+    pub dev: bool,
+    pub build: bool,
+    pub std: bool,
+    pub build_scripts: bool,
+    pub examples: bool,
+    pub tests: bool,
+    pub benches: bool,
+    pub jobs: Option<usize>,
+    pub cache: bool,
+    pub match_mode: MatchMode,
+    pub list: bool,
+    pub message_format: MessageFormat,
+    pub check: bool,
+    pub force: bool,
+    pub verbosity: Verbosity,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            subcommand: Subcommand::Tag,
+            output: None,
+            in_target_dir: false,
+            split: false,
+            format: Format::Ctags,
+            append: false,
+            priority: Tier::ALL.to_vec(),
+            qualified: false,
+            public_only: false,
+            relative: false,
+            remap_path_prefix: vec![],
+            manifest_path: None,
+            backend: Backend::Native,
+            ctags: "ctags".to_owned(),
+            ctags_args: vec![],
+            sqlite3: "sqlite3".to_owned(),
+            features: vec![],
+            all_features: false,
+            no_default_features: false,
+            filter_platform: None,
+            include: vec![],
+            exclude: vec![],
+            depth: None,
+            dev: true,
+            build: true,
+            std: false,
+            build_scripts: false,
+            examples: false,
+            tests: false,
+            benches: false,
+            jobs: None,
+            cache: true,
+            match_mode: MatchMode::Prefix,
+            list: false,
+            message_format: MessageFormat::Human,
+            check: false,
+            force: false,
+            verbosity: Verbosity::Normal,
+        }
+    }
+}
+
+impl Config {
+    /// Path of the tags file to write: the given one, or one named after the
+    /// format in the workspace root or target directory. With `--split`, the
+    /// directory of the tags files, `tags` in the target directory by default.
+    pub fn output(&self, workspace_root: &Path, target_directory: &Path) -> PathBuf {
+        if let Some(output) = &self.output {
+            return output.clone();
+        }
+        if self.split {
+            return target_directory.join("tags");
+        }
+
+        let dir = match self.in_target_dir {
+            true => target_directory,
+            false => workspace_root,
+        };
+        dir.join(self.format.default_file_name())
+    }
+
+    /// Rank of the tags of packages of `tier`, lower ranks coming first.
+    pub fn rank(&self, tier: Tier) -> usize {
+        self.priority
+            .iter()
+            .position(|&t| t == tier)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Whether a target of the given kinds gets tagged.
+    pub fn tags_target(&self, kinds: &[String]) -> bool {
+        kinds.iter().any(|kind| match kind.as_str() {
+            "custom-build" => self.build_scripts,
+            "example" => self.examples,
+            "test" => self.tests,
+            "bench" => self.benches,
+            _ => true,
+        })
+    }
+}
+
+pub enum Action {
+    Run(Box<Config>),
+    Help,
+    Version,
+}
+
+/// Parse the process arguments.
+///
+/// Cargo invokes subcommands as `cargo-symbols symbols [ARGS]`, while running
+/// the binary directly gives `cargo-symbols [ARGS]`, so a leading `symbols` is
+/// skipped.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Action, AnyError> {
+    parse_over(vec![], args)
+}
+
+/// Parse the process arguments on top of `settings`, the options standing
+/// for the metadata tables. List options given in the arguments replace the
+/// configured lists instead of adding to them.
+fn parse_over(
+    settings: Vec<String>,
+    args: impl IntoIterator<Item = String>,
+) -> Result<Action, AnyError> {
+    let mut args = args.into_iter().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("symbols") {
+        args.next();
+    }
+    let configured = settings.len();
+    let mut args = settings.into_iter().chain(args).enumerate();
+
+    let mut config = Config::default();
+    let mut positional = vec![];
+    // list options given in the arguments so far
+    let mut replaced: Vec<&str> = vec![];
+    // the short switches following one in the same argument, as in `-vq`
+    let mut cluster = None;
+
+    while let Some((i, arg)) = cluster.take().or_else(|| args.next()) {
+        // split `--flag=value`, `-fvalue` and `-f=value` into their flag and
+        // inline value, and whether it follows a `=`
+        let (flag, inline, assigned) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_owned(), Some(value.to_owned()), true)
+            }
+            _ if arg.len() > 2
+                && arg.is_char_boundary(2)
+                && !arg.starts_with("--")
+                && arg.starts_with('-') =>
+            {
+                let (flag, value) = arg.split_at(2);
+                let (value, assigned) = match value.strip_prefix('=') {
+                    Some(value) => (value, true),
+                    None => (value, false),
+                };
+                (flag.to_owned(), Some(value.to_owned()), assigned)
+            }
+            _ => (arg, None, false),
+        };
+
+        let mut consumed = false;
+        let mut value = || {
+            consumed = true;
+            inline
+                .clone()
+                .or_else(|| args.next().map(|(_, arg)| arg))
+                .ok_or_else(|| format!("option `{flag}` requires a value"))
+        };
+        // whether the list option `key` starts over, being given in the
+        // arguments for the first time
+        let mut replace = |key| {
+            i >= configured && !replaced.contains(&key) && {
+                replaced.push(key);
+                true
+            }
+        };
+
+        match flag.as_str() {
+            "-o" | "--output" => config.output = Some(value()?.into()),
+            "--in-target-dir" => config.in_target_dir = true,
+            "--no-in-target-dir" => config.in_target_dir = false,
+            "--split" => config.split = true,
+            "--no-split" => config.split = false,
+            "--format" => {
+                config.format = match value()?.as_str() {
+                    "ctags" => Format::Ctags,
+                    "etags" => Format::Etags,
+                    "json" => Format::Json,
+                    "jsonl" => Format::Jsonl,
+                    "sqlite" => Format::Sqlite,
+                    other => return Err(format!("unknown format `{other}`").into()),
+                }
+            }
+            "--append" => config.append = true,
+            "--priority" => {
+                let tiers = value()?;
+                let mut priority = vec![];
+                for name in tiers.split(',').filter(|t| !t.is_empty()) {
+                    let tier = Tier::from_name(name)
+                        .ok_or_else(|| format!("unknown priority tier `{name}`"))?;
+                    if !priority.contains(&tier) {
+                        priority.push(tier);
+                    }
+                }
+                // the tiers left out follow, in their usual order
+                let rest: Vec<_> = Tier::ALL
+                    .into_iter()
+                    .filter(|tier| !priority.contains(tier))
+                    .collect();
+                priority.extend(rest);
+                config.priority = priority;
+            }
+            "--qualified" => config.qualified = true,
+            "--no-qualified" => config.qualified = false,
+            "--public-only" => config.public_only = true,
+            "--no-public-only" => config.public_only = false,
+            "--relative" => config.relative = true,
+            "--no-relative" => config.relative = false,
+            "--remap-path-prefix" => {
+                let mapping = value()?;
+                let (from, to) = mapping.rsplit_once('=').ok_or_else(|| {
+                    format!("`--remap-path-prefix` expects FROM=TO, found `{mapping}`")
+                })?;
+                if replace("remap-path-prefix") {
+                    config.remap_path_prefix.clear();
+                }
+                config.remap_path_prefix.push((from.into(), to.into()));
+            }
+            "--manifest-path" => config.manifest_path = Some(value()?.into()),
+            "--backend" => {
+                config.backend = match value()?.as_str() {
+                    "native" => Backend::Native,
+                    "ctags" => Backend::Ctags,
+                    other => return Err(format!("unknown backend `{other}`").into()),
+                }
+            }
+            "--ctags" => config.ctags = value()?,
+            "--ctags-arg" => {
+                let arg = value()?;
+                if replace("ctags-args") {
+                    config.ctags_args.clear();
+                }
+                config.ctags_args.push(arg);
+            }
+            "--sqlite3" => config.sqlite3 = value()?,
+            "-F" | "--features" => {
+                let features = value()?;
+                let features = features.split([' ', ',']).filter(|f| !f.is_empty());
+                config.features.extend(features.map(str::to_owned));
+            }
+            "--all-features" => config.all_features = true,
+            "--no-default-features" => config.no_default_features = true,
+            "--filter-platform" => config.filter_platform = Some(value()?),
+            "--include" | "-p" | "--package" => {
+                let spec = PackageSpec::parse(&value()?)?;
+                if replace("include") {
+                    config.include.clear();
+                }
+                config.include.push(spec);
+            }
+            "--exclude" => {
+                let spec = PackageSpec::parse(&value()?)?;
+                if replace("exclude") {
+                    config.exclude.clear();
+                }
+                config.exclude.push(spec);
+            }
+            "--depth" => {
+                let depth = value()?;
+                let depth = depth
+                    .parse()
+                    .map_err(|_| format!("invalid depth `{depth}`"))?;
+                config.depth = Some(depth);
+            }
+            "--dev" => config.dev = true,
+            "--no-dev" => config.dev = false,
+            "--build" => config.build = true,
+            "--no-build" => config.build = false,
+            "--std" => config.std = true,
+            "--no-std" => config.std = false,
+            "--build-scripts" => config.build_scripts = true,
+            "--no-build-scripts" => config.build_scripts = false,
+            "--examples" => config.examples = true,
+            "--no-examples" => config.examples = false,
+            "--tests" => config.tests = true,
+            "--no-tests" => config.tests = false,
+            "--benches" => config.benches = true,
+            "--no-benches" => config.benches = false,
+            "-j" | "--jobs" => {
+                let jobs = value()?;
+                match jobs.parse() {
+                    Ok(0) | Err(_) => return Err(format!("invalid number of jobs `{jobs}`").into()),
+                    Ok(jobs) => config.jobs = Some(jobs),
+                }
+            }
+            "--match" => {
+                config.match_mode = match value()?.as_str() {
+                    "exact" => MatchMode::Exact,
+                    "prefix" => MatchMode::Prefix,
+                    "substring" => MatchMode::Substring,
+                    "fuzzy" => MatchMode::Fuzzy,
+                    other => return Err(format!("unknown match mode `{other}`").into()),
+                }
+            }
+            "--list" => config.list = true,
+            "--message-format" => {
+                config.message_format = match value()?.as_str() {
+                    "human" => MessageFormat::Human,
+                    "json" => MessageFormat::Json,
+                    other => return Err(format!("unknown message format `{other}`").into()),
+                }
+            }
+            "--check" => config.check = true,
+            "--force" => config.force = true,
+            "--cache" => config.cache = true,
+            "--no-cache" => config.cache = false,
+            "-v" | "--verbose" => config.verbosity = Verbosity::Verbose,
+            "-q" | "--quiet" => config.verbosity = Verbosity::Quiet,
+            "-h" | "--help" => return Ok(Action::Help),
+            "-V" | "--version" => return Ok(Action::Version),
+            _ if !flag.starts_with('-') => positional.push(flag.clone()),
+            _ => return Err(format!("unexpected argument `{flag}`\n\n{USAGE}").into()),
+        }
+
+        // the inline value of a switch is the switches after it, `-vq`
+        // standing for `-v -q`
+        match inline {
+            Some(_) if consumed => (),
+            Some(rest) if !assigned && !flag.starts_with("--") => {
+                cluster = Some((i, format!("-{rest}")))
+            }
+            Some(_) => return Err(format!("option `{flag}` doesn't take a value").into()),
+            None => (),
+        }
+    }
+
+    config.subcommand = match &positional[..] {
+        [] => Subcommand::Tag,
+        [command, pattern] if command == "find" => Subcommand::Find(pattern.clone()),
+        [command] if command == "find" => return Err("`find` requires a pattern".into()),
+        [command, ..] if command == "find" => {
+            return Err(format!("unexpected argument `{}`", positional[2]).into());
+        }
+        [command] if command == "lsp" => Subcommand::Lsp,
+        [command, other, ..] if command == "lsp" => {
+            return Err(format!("unexpected argument `{other}`").into());
+        }
+        [other, ..] => return Err(format!("unknown command `{other}`\n\n{USAGE}").into()),
+    };
+
+    match config.subcommand {
+        Subcommand::Find(_) if config.format != Format::Ctags => {
+            return Err("`find` only reads tags files of the ctags format".into());
+        }
+        Subcommand::Lsp if config.format != Format::Ctags => {
+            return Err("`lsp` only reads tags files of the ctags format".into());
+        }
+        _ => (),
+    }
+    if config.split && config.append {
+        return Err("`--append` cannot be used with `--split`".into());
+    }
+    if config.split && !config.format.is_tags_file() {
+        return Err("`--split` can only be used with the ctags and etags formats".into());
+    }
+    if config.append && config.format == Format::Sqlite {
+        return Err("`--append` cannot be used with the sqlite format".into());
+    }
+
+    Ok(Action::Run(Box::new(config)))
+}
+
+/// Settings of the metadata tables that are switched on and off.
+const FLAG_SETTINGS: &[&str] = &[
+    "split",
+    "in-target-dir",
+    "relative",
+    "qualified",
+    "public-only",
+    "dev",
+    "build",
+    "std",
+    "build-scripts",
+    "examples",
+    "tests",
+    "benches",
+    "cache",
+];
+
+/// Settings of the metadata tables that take values, lists giving the option
+/// once per item.
+const VALUE_SETTINGS: &[&str] = &[
+    "output",
+    "format",
+    "remap-path-prefix",
+    "priority",
+    "backend",
+    "ctags",
+    "ctags-args",
+    "sqlite3",
+    "include",
+    "exclude",
+    "depth",
+    "jobs",
+];
+
+/// The options standing for a `symbols` metadata table, `section` naming it
+/// in errors. Relative output paths are taken from `workspace_root`.
+fn table_args(table: &Json, section: &str, workspace_root: &Path) -> Result<Vec<String>, AnyError> {
+    let Json::Obj(entries) = table else {
+        return Err(format!("[{section}.metadata.symbols] is not a table").into());
+    };
+
+    let mut args = vec![];
+    for (key, value) in entries {
+        let invalid = || format!("invalid value for `{key}` in [{section}.metadata.symbols]");
+
+        if FLAG_SETTINGS.contains(&key.as_str()) {
+            match value {
+                Json::Bool(true) => args.push(format!("--{key}")),
+                Json::Bool(false) => args.push(format!("--no-{key}")),
+                _ => return Err(invalid().into()),
+            }
+            continue;
+        }
+        if !VALUE_SETTINGS.contains(&key.as_str()) {
+            return Err(format!("unknown setting `{key}` in [{section}.metadata.symbols]").into());
+        }
+
+        let flag = match key.as_str() {
+            "ctags-args" => "--ctags-arg".to_owned(),
+            key => format!("--{key}"),
+        };
+        let values = match value {
+            Json::List(items) if key == "priority" => {
+                let tiers: Option<Vec<_>> = items.iter().map(Json::as_str).collect();
+                vec![tiers.ok_or_else(invalid)?.join(",")]
+            }
+            Json::List(items) => items
+                .iter()
+                .map(|item| item.as_str().map(str::to_owned))
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?,
+            Json::Str(value) if key == "output" => {
+                vec![workspace_root.join(value).to_string_lossy().into_owned()]
+            }
+            Json::Str(value) => vec![value.clone()],
+            Json::Number(n) => vec![n.to_string()],
+            _ => return Err(invalid().into()),
+        };
+        for value in values {
+            args.extend([flag.clone(), value]);
+        }
+    }
+
+    Ok(args)
+}
+
+/// Parse the process arguments on top of the settings in the `symbols`
+/// tables of the workspace and root package metadata, so that options given
+/// on the command line override them.
+pub fn parse_with_metadata(
+    args: Vec<String>,
+    metadata: &Json,
+    workspace_root: &Path,
+) -> Result<Action, AnyError> {
+    let mut settings = vec![];
+
+    let workspace = metadata.get("metadata").and_then(|m| m.get("symbols"));
+    if let Some(table) = workspace {
+        settings.extend(table_args(table, "workspace", workspace_root)?);
+    }
+
+    // the package of the workspace root manifest, not that of the current
+    // directory, so that the settings are the same wherever cargo runs
+    let root_manifest = workspace_root.join("Cargo.toml");
+    let packages = metadata.get("packages").map_or(&[][..], Json::items);
+    let package = packages.iter().find(|package| {
+        let manifest = package.get("manifest_path").and_then(Json::as_str);
+        manifest.is_some_and(|manifest| Path::new(manifest) == root_manifest)
+    });
+    let table = package
+        .and_then(|package| package.get("metadata"))
+        .and_then(|m| m.get("symbols"));
+    if let Some(table) = table {
+        settings.extend(table_args(table, "package", workspace_root)?);
+    }
+
+    parse_over(settings, args)
+}
+
+pub fn print_help() {
+    print!("{USAGE}");
+}
+
+pub fn print_version() {
+    println!("cargo-symbols {}", env!("CARGO_PKG_VERSION"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Config {
+        let argv = ["cargo-symbols", "symbols"].iter().chain(args);
+        match parse(argv.map(|arg| arg.to_string())) {
+            Ok(Action::Run(config)) => *config,
+            _ => panic!("{args:?} did not parse"),
+        }
+    }
+
+    #[test]
+    fn inline_values() {
+        for args in [
+            &["-o", "x.tags"][..],
+            &["-ox.tags"],
+            &["-o=x.tags"],
+            &["--output", "x.tags"],
+            &["--output=x.tags"],
+        ] {
+            assert_eq!(config(args).output, Some(PathBuf::from("x.tags")));
+        }
+        assert_eq!(config(&["-j=4"]).jobs, Some(4));
+    }
+
+    #[test]
+    fn switches() {
+        assert!(config(&["--split"]).split);
+        assert!(!config(&["--split", "--no-split"]).split);
+        for args in [
+            &["--split=false"][..],
+            &["--append=no"],
+            &["-v=1"],
+            &["-q="],
+        ] {
+            let err = parse(["cargo-symbols"].iter().chain(args).map(|a| a.to_string()));
+            let Err(err) = err else {
+                panic!("{args:?} parsed");
+            };
+            assert!(err.to_string().contains("doesn't take a value"), "{err}");
+        }
+    }
+
+    #[test]
+    fn clustered_switches() {
+        assert_eq!(config(&["-vq"]).verbosity, Verbosity::Quiet);
+        assert_eq!(config(&["-qv"]).verbosity, Verbosity::Verbose);
+        // the last switch of a cluster may take a value
+        let config = config(&["-qj", "3"]);
+        assert_eq!((config.verbosity, config.jobs), (Verbosity::Quiet, Some(3)));
+        assert!(matches!(
+            parse(["cargo-symbols", "-qx"].map(str::to_owned)),
+            Err(err) if err.to_string().starts_with("unexpected argument `-x`")
+        ));
+        assert!(matches!(
+            parse(["cargo-symbols", "-vh"].map(str::to_owned)),
+            Ok(Action::Help)
+        ));
+    }
+
+    #[test]
+    fn priority() {
+        use Tier::*;
+        assert_eq!(config(&[]).priority, Tier::ALL);
+        // the tiers left out follow in their usual order
+        let config = config(&["--priority", "std,direct,std"]);
+        assert_eq!(config.priority, [Std, Direct, Workspace, Transitive]);
+        assert_eq!(config.rank(Std), 0);
+        assert_eq!(config.rank(Transitive), 3);
+        assert!(matches!(
+            parse(["cargo-symbols", "--priority=dev"].map(str::to_owned)),
+            Err(err) if err.to_string() == "unknown priority tier `dev`"
+        ));
+    }
+}
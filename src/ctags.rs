@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cli::Config,
+    native, run,
+    tags::{line_offsets, Kind, Tag, Visibility},
+    AnyError,
+};
+
+/// Run ctags on `files`, given with their module paths, and convert its
+/// output into tags.
+pub fn tag_files(files: &[(PathBuf, String)], config: &Config) -> Result<Vec<Tag>, AnyError> {
+    let mut list = vec![];
+    for (file, _) in files {
+        writeln!(list, "{}", file.display())?;
+    }
+    let modules: HashMap<&Path, &str> = files
+        .iter()
+        .map(|(file, module)| (file.as_path(), module.as_str()))
+        .collect();
+
+    let mut command = Command::new(&config.ctags);
+    command.args(&config.ctags_args);
+    command.args(["--languages=Rust", "--fields=+nse", "-L", "-", "-f", "-"]);
+    let stdout = run(command, list, config)?;
+    convert(&String::from_utf8_lossy(&stdout), &modules)
+}
+
+/// Tags of the ctags output `stdout`, whose files have the given module
+/// paths.
+fn convert(stdout: &str, modules: &HashMap<&Path, &str>) -> Result<Vec<Tag>, AnyError> {
+    // source lines of every file seen, for the patterns and offsets
+    let mut sources: HashMap<PathBuf, Vec<(usize, String)>> = HashMap::new();
+    let mut tags = vec![];
+    // tags in enums, traits and impl blocks, with the kind of their scope
+    let mut members = vec![];
+
+    for line in stdout.lines().filter(|line| !line.starts_with("!_TAG_")) {
+        let mut fields = line.split('\t');
+        let (Some(name), Some(file)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+
+        // everything after the `;"` closing the address
+        let mut kind = None;
+        let mut line_number: Option<usize> = None;
+        let mut end_line = None;
+        let mut parent = None;
+        for field in fields.skip_while(|field| !field.ends_with(";\"")).skip(1) {
+            match field.split_once(':') {
+                Some(("line", n)) => line_number = n.parse().ok(),
+                Some(("end", n)) => end_line = n.parse().ok(),
+                Some(("kind", k)) => kind = k.chars().next().and_then(Kind::from_letter),
+                // the scope field is named after the kind of the scope
+                Some((
+                    scope_kind @ ("module" | "struct" | "enum" | "interface" | "implementation"),
+                    scope,
+                )) => parent = Some((scope_kind, scope)),
+                None => kind = field.chars().next().and_then(Kind::from_letter),
+                _ => (),
+            }
+        }
+        let (Some(kind), Some(line_number)) = (kind, line_number) else {
+            continue;
+        };
+
+        let file = PathBuf::from(file);
+        if !sources.contains_key(&file) {
+            let source = fs::read(&file)?;
+            let lines = line_offsets(&String::from_utf8_lossy(&source));
+            sources.insert(file.clone(), lines);
+        }
+        let lines = &sources[&file];
+        let Some((offset, pattern)) = line_number
+            .checked_sub(1)
+            .and_then(|i| lines.get(i))
+            .cloned()
+        else {
+            continue;
+        };
+
+        let mut scope = modules
+            .get(file.as_path())
+            .copied()
+            .unwrap_or_default()
+            .to_owned();
+        if let Some((scope_kind, parent)) = parent {
+            scope += "::";
+            scope += parent;
+            if matches!(scope_kind, "enum" | "interface" | "implementation") {
+                members.push((tags.len(), scope_kind));
+            }
+        }
+
+        // the visibility is read from the source, with the attributes on the
+        // lines before for `#[macro_export]`
+        let index = line_number - 1;
+        let first = (0..index)
+            .rev()
+            .take_while(|&i| lines[i].1.trim_start().starts_with("#["))
+            .last()
+            .unwrap_or(index);
+        let declaration: Vec<_> = lines[first..=index]
+            .iter()
+            .map(|(_, line)| line.as_str())
+            .collect();
+        let visibility = match kind {
+            // impl blocks have no visibility of their own
+            Kind::Impl => Some(Visibility::Public),
+            _ => native::declared_visibility(&declaration.join("\n"), name),
+        };
+
+        // ctags only gives the line, the name is its first identifier on it
+        let column = native::declared_column(&pattern, name)
+            .or_else(|| pattern.find(name).map(|i| pattern[..i].chars().count() + 1))
+            .unwrap_or(1);
+
+        tags.push(Tag {
+            name: name.to_owned(),
+            file,
+            line: line_number,
+            column,
+            end_line: end_line.unwrap_or(line_number),
+            offset,
+            kind,
+            pattern,
+            scope,
+            visibility,
+        });
+    }
+
+    // members of enums and traits have their visibility, those of trait
+    // impls are public
+    let mut containers: HashMap<(&Path, String), Vec<&Tag>> = HashMap::new();
+    for tag in &tags {
+        if matches!(tag.kind, Kind::Enum | Kind::Trait | Kind::Impl) {
+            let key = (tag.file.as_path(), tag.qualified_name());
+            containers.entry(key).or_default().push(tag);
+        }
+    }
+    let inherited: Vec<_> = members
+        .into_iter()
+        .filter_map(|(i, scope_kind)| {
+            let member = &tags[i];
+            let container = containers
+                .get(&(member.file.as_path(), member.scope.clone()))?
+                .iter()
+                .find(|tag| tag.line <= member.line && member.line <= tag.end_line)?;
+            let visibility = match scope_kind {
+                "implementation" if native::declares_trait_impl(&container.pattern) => {
+                    Visibility::Public
+                }
+                "implementation" => return None,
+                _ => container.visibility?,
+            };
+            Some((i, visibility))
+        })
+        .collect();
+    for (i, visibility) in inherited {
+        tags[i].visibility = Some(visibility);
+    }
+
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversion() {
+        let dir = std::env::temp_dir().join(format!("cargo-symbols-ctags-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.rs");
+        let source = "pub fn n() {}\n\
+                      pub enum E {\n    A,\n}\n\
+                      const USAGE: &str = \"\\\n\
+                      #[macro_export]\n\
+                      macro_rules! m { () => {} }\n";
+        fs::write(&file, source).unwrap();
+
+        let path = file.display();
+        let stdout = format!(
+            "!_TAG_FILE_FORMAT\t2\t/extended format/;\"\n\
+             n\t{path}\t/^pub fn n() {{}}$/;\"\tf\tline:1\n\
+             E\t{path}\t/^pub enum E {{$/;\"\tkind:g\tline:2\tend:4\n\
+             A\t{path}\t/^    A,$/;\"\te\tline:3\tenum:E\n\
+             USAGE\t{path}\t/^const USAGE: &str = \"\\\\$/;\"\tC\tline:5\n\
+             m\t{path}\t/^macro_rules! m {{ () => {{}} }}$/;\"\tM\tline:7\n"
+        );
+        let modules = HashMap::from([(file.as_path(), "krate")]);
+        let tags = convert(&stdout, &modules).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let tags: Vec<_> = tags
+            .iter()
+            .map(|tag| {
+                let visibility = tag.visibility.map(Visibility::name);
+                let position = (tag.line, tag.column, tag.end_line);
+                (tag.qualified_name(), tag.kind, position, visibility)
+            })
+            .collect();
+        assert_eq!(
+            tags,
+            [
+                (
+                    "krate::n".to_owned(),
+                    Kind::Function,
+                    (1, 8, 1),
+                    Some("pub")
+                ),
+                ("krate::E".to_owned(), Kind::Enum, (2, 10, 4), Some("pub")),
+                (
+                    "krate::E::A".to_owned(),
+                    Kind::Variant,
+                    (3, 5, 3),
+                    Some("pub")
+                ),
+                (
+                    "krate::USAGE".to_owned(),
+                    Kind::Const,
+                    (5, 7, 5),
+                    Some("private")
+                ),
+                ("krate::m".to_owned(), Kind::Macro, (7, 14, 7), Some("pub")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use crate::metadata::{Package, SourceKind};
+
+/// Whether `name` matches `glob`, where `*` stands for any run of characters
+/// and `?` for any single character.
+fn glob_match(glob: &str, name: &str) -> bool {
+    let (glob, name): (Vec<char>, Vec<char>) = (glob.chars().collect(), name.chars().collect());
+    let (mut g, mut n) = (0, 0);
+    // position of the last `*` and of the name when it was reached
+    let mut star = None;
+
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, n));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            // let the last `*` take one more character
+            _ => match star {
+                Some((star_g, star_n)) => {
+                    g = star_g + 1;
+                    n = star_n + 1;
+                    star = Some((star_g, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// An identifier of a pre-release, numeric ones sorting first.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+enum Identifier {
+    Numeric(u64),
+    Alphanumeric(String),
+}
+
+/// A version as compared by requirements: the release, whether it is one,
+/// and the pre-release identifiers, pre-releases sorting before their
+/// release.
+type Version = (u64, u64, u64, bool, Vec<Identifier>);
+
+/// The release and pre-release identifiers of `version`, build metadata
+/// left out. The release may have fewer than three parts.
+fn split_version(version: &str) -> Option<(Vec<&str>, Vec<Identifier>)> {
+    let version = version.split('+').next()?;
+    let (release, pre) = match version.split_once('-') {
+        Some((release, pre)) => (release, Some(pre)),
+        None => (version, None),
+    };
+    let pre = pre.map_or(Some(vec![]), |pre| {
+        pre.split('.')
+            .map(|id| match id.parse() {
+                _ if id.is_empty() => None,
+                Ok(n) => Some(Identifier::Numeric(n)),
+                Err(_) => Some(Identifier::Alphanumeric(id.to_owned())),
+            })
+            .collect()
+    })?;
+    Some((release.split('.').collect(), pre))
+}
+
+fn parse_version(version: &str) -> Option<Version> {
+    let (release, pre) = split_version(version)?;
+    let mut parts = release.iter().map(|part| part.parse());
+    let (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    Some((major, minor, patch, pre.is_empty(), pre))
+}
+
+/// One comparator of a requirement, as the range of versions it accepts.
+#[derive(Debug)]
+struct Comparator {
+    /// Inclusive lower bound.
+    min: Version,
+    /// Exclusive upper bound.
+    max: Option<Version>,
+    /// Release whose pre-releases the comparator names, as in `>=1.0.0-rc.1`.
+    pre: Option<(u64, u64, u64)>,
+}
+
+impl Comparator {
+    /// Parse a comparator such as `^1.2`, `>=0.4.1`, `~1`, `1.*` or
+    /// `=1.0.0-rc.1`, with the meaning cargo gives it.
+    fn parse(comparator: &str) -> Option<Self> {
+        let op_len = comparator
+            .find(|c: char| !matches!(c, '=' | '>' | '<' | '~' | '^'))
+            .unwrap_or(comparator.len());
+        let (op, version) = comparator.split_at(op_len);
+        let (release, pre) = split_version(version.trim())?;
+
+        let mut parts = vec![];
+        let mut wildcard = false;
+        for part in release {
+            match part {
+                "*" | "x" | "X" => {
+                    wildcard = true;
+                    break;
+                }
+                part => parts.push(part.parse::<u64>().ok()?),
+            }
+        }
+        if parts.len() > 3 || (parts.is_empty() && !wildcard) {
+            return None;
+        }
+        // only full versions can have a pre-release
+        if !pre.is_empty() && (wildcard || parts.len() < 3) {
+            return None;
+        }
+        // `1.2.*` means exactly `=1.2`
+        let op = match (op, wildcard) {
+            ("", true) => "=",
+            (op, _) => op,
+        };
+
+        let release = |v: [u64; 3]| (v[0], v[1], v[2], true, vec![]);
+        let mut padded = [0; 3];
+        padded[..parts.len()].copy_from_slice(&parts);
+        let pad = (padded[0], padded[1], padded[2], pre.is_empty(), pre.clone());
+        // the first version past all of those the given parts match
+        let bump = |len: usize| -> Option<Version> {
+            let mut bumped = padded;
+            let i = len.checked_sub(1)?;
+            bumped[i] += 1;
+            bumped[i + 1..].fill(0);
+            Some(release(bumped))
+        };
+        // the first version past the given pre-release: none sorts between
+        // it and the same identifiers followed by the lowest one
+        let after_pre = || {
+            let mut next = pad.clone();
+            next.4.push(Identifier::Numeric(0));
+            next
+        };
+        let zero = (0, 0, 0, false, vec![]);
+
+        let (min, max) = match op {
+            "=" if !pre.is_empty() => (pad.clone(), Some(after_pre())),
+            "=" => (pad.clone(), bump(parts.len())),
+            ">" if !pre.is_empty() => (after_pre(), None),
+            ">" => (bump(parts.len())?, None),
+            ">=" => (pad.clone(), None),
+            "<" => (zero, Some(pad.clone())),
+            "<=" if !pre.is_empty() => (zero, Some(after_pre())),
+            "<=" => (zero, bump(parts.len())),
+            "~" => (pad.clone(), bump(parts.len().clamp(1, 2))),
+            "^" | "" => {
+                // up to the first non-zero part given
+                let significant = parts
+                    .iter()
+                    .position(|&part| part != 0)
+                    .map_or(parts.len(), |i| i + 1);
+                (pad.clone(), bump(significant.min(parts.len())))
+            }
+            _ => return None,
+        };
+        let pre = (!pre.is_empty()).then_some((padded[0], padded[1], padded[2]));
+        Some(Self { min, max, pre })
+    }
+
+    fn matches(&self, version: &Version) -> bool {
+        self.min <= *version && self.max.as_ref().is_none_or(|max| version < max)
+    }
+}
+
+/// Whether `version` meets all the comparators of `req`. As with cargo,
+/// pre-releases only do when a comparator names a pre-release of the same
+/// release.
+fn meets(req: &[Comparator], version: &Version) -> bool {
+    let (major, minor, patch, is_release, _) = *version;
+    (is_release || req.iter().any(|c| c.pre == Some((major, minor, patch))))
+        && req.iter().all(|c| c.matches(version))
+}
+
+/// A package selection: `[KIND:]NAME[@REQ]`, where `KIND` is a source kind
+/// (`registry`, `git` or `path`), `NAME` a glob and `REQ` a version
+/// requirement such as `^1.2` or `>=0.4, <0.6`.
+#[derive(Debug)]
+pub struct PackageSpec {
+    kind: Option<SourceKind>,
+    name: String,
+    req: Vec<Comparator>,
+}
+
+impl PackageSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (kind, rest) = match spec.split_once(':') {
+            Some((kind, rest)) => match SourceKind::from_name(kind) {
+                Some(kind) => (Some(kind), rest),
+                None => return Err(format!("unknown source kind `{kind}` in `{spec}`")),
+            },
+            None => (None, spec),
+        };
+
+        let (name, req) = rest.split_once('@').unwrap_or((rest, ""));
+        let req = req
+            .split(',')
+            .map(str::trim)
+            .filter(|comparator| !comparator.is_empty() && *comparator != "*")
+            .map(|comparator| {
+                Comparator::parse(comparator).ok_or_else(|| {
+                    format!("invalid version requirement `{comparator}` in `{spec}`")
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            kind,
+            name: match name.is_empty() {
+                true => "*".to_owned(),
+                false => name.to_owned(),
+            },
+            req,
+        })
+    }
+
+    pub fn matches(&self, package: &Package) -> bool {
+        let version = parse_version(&package.version);
+        self.kind.is_none_or(|kind| kind == package.source_kind())
+            && glob_match(&self.name, &package.name)
+            && (self.req.is_empty() || version.is_some_and(|v| meets(&self.req, &v)))
+    }
+}
+
+/// Whether `package` is selected by the `include` specs, all packages being
+/// when there are none, and not by the `exclude` ones.
+pub fn selects(include: &[PackageSpec], exclude: &[PackageSpec], package: &Package) -> bool {
+    (include.is_empty() || include.iter().any(|spec| spec.matches(package)))
+        && !exclude.iter().any(|spec| spec.matches(package))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `version` meets the requirement `req`.
+    fn meets_req(req: &str, version: &str) -> bool {
+        let req: Vec<_> = req
+            .split(',')
+            .map(|c| Comparator::parse(c.trim()).unwrap())
+            .collect();
+        meets(&req, &parse_version(version).unwrap())
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*-sys", "openssl-sys"));
+        assert!(!glob_match("*-sys", "openssl-sys2"));
+        assert!(glob_match("windows-*", "windows-targets"));
+        assert!(glob_match("s?rde*", "serde_json"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(glob_match("*ab", "aab"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("serde", "serde_json"));
+        assert!(glob_match("é*", "éa"));
+    }
+
+    #[test]
+    fn caret() {
+        assert!(meets_req("^1.2", "1.2.0"));
+        assert!(meets_req("^1.2", "1.9.9"));
+        assert!(!meets_req("^1.2", "1.1.9"));
+        assert!(!meets_req("^1.2", "2.0.0"));
+        assert!(meets_req("1.2.3", "1.2.3"));
+        assert!(!meets_req("1.2.3", "1.2.2"));
+        assert!(meets_req("^0.2.3", "0.2.9"));
+        assert!(!meets_req("^0.2.3", "0.3.0"));
+        assert!(meets_req("^0.0.3", "0.0.3"));
+        assert!(!meets_req("^0.0.3", "0.0.4"));
+        assert!(meets_req("^0.0", "0.0.7"));
+        assert!(!meets_req("^0.0", "0.1.0"));
+        assert!(meets_req("^0", "0.9.0"));
+        assert!(!meets_req("^0", "1.0.0"));
+    }
+
+    #[test]
+    fn operators() {
+        assert!(meets_req("~1", "1.9.0"));
+        assert!(!meets_req("~1", "2.0.0"));
+        assert!(meets_req("~1.2", "1.2.9"));
+        assert!(!meets_req("~1.2", "1.3.0"));
+        assert!(meets_req("~1.2.3", "1.2.9"));
+        assert!(!meets_req("~1.2.3", "1.2.2"));
+        assert!(meets_req("1.*", "1.5.0"));
+        assert!(!meets_req("1.*", "2.0.0"));
+        assert!(meets_req("1.2.x", "1.2.7"));
+        assert!(!meets_req("1.2.x", "1.3.0"));
+        assert!(meets_req("=1.2", "1.2.5"));
+        assert!(!meets_req("=1.2", "1.3.0"));
+        assert!(meets_req(">1.2", "1.3.0"));
+        assert!(!meets_req(">1.2", "1.2.5"));
+        assert!(meets_req("<=1.2", "1.2.5"));
+        assert!(!meets_req("<=1.2", "1.3.0"));
+        assert!(meets_req(">=0.4, <0.6", "0.5.1"));
+        assert!(!meets_req(">=0.4, <0.6", "0.6.0"));
+        assert!(meets_req("^1", "1.0.0+build.5"));
+    }
+
+    #[test]
+    fn pre_releases() {
+        // only matched by comparators naming a pre-release of their release
+        assert!(!meets_req("^1", "1.5.0-beta"));
+        assert!(!meets_req("^1", "2.0.0-alpha"));
+        assert!(!meets_req("^1.0.0", "1.0.0-rc.1"));
+        assert!(!meets_req("<2", "2.0.0-alpha"));
+        assert!(meets_req("^1.0.0-rc.1", "1.0.0-rc.2"));
+        assert!(meets_req("^1.0.0-rc.1", "1.0.0"));
+        assert!(meets_req("^1.0.0-rc.1", "1.3.0"));
+        assert!(!meets_req("^1.0.0-rc.1", "1.0.0-rc.0"));
+        assert!(!meets_req("^1.0.0-rc.1", "1.3.0-rc.1"));
+        // identifiers compare numerically, numeric before alphanumeric, and
+        // more of them after fewer
+        assert!(meets_req(">=1.0.0-alpha.2", "1.0.0-alpha.10"));
+        assert!(meets_req(">=1.0.0-alpha.2", "1.0.0-alpha.beta"));
+        assert!(meets_req(">=1.0.0-alpha", "1.0.0-alpha.1"));
+        assert!(!meets_req(">=1.0.0-beta", "1.0.0-alpha.9"));
+        assert!(meets_req("=1.0.0-rc.1", "1.0.0-rc.1"));
+        assert!(!meets_req("=1.0.0-rc.1", "1.0.0-rc.1.0"));
+        assert!(!meets_req("=1.0.0-rc.1", "1.0.0"));
+        assert!(meets_req(">1.0.0-rc.1", "1.0.0-rc.1.0"));
+        assert!(!meets_req(">1.0.0-rc.1", "1.0.0-rc.1"));
+        assert!(meets_req("<=1.0.0-rc.1", "1.0.0-rc.1"));
+        assert!(!meets_req("<=1.0.0-rc.1", "1.0.0-rc.2"));
+    }
+
+    #[test]
+    fn invalid_requirements() {
+        for invalid in [
+            "", "^", "1.2.3.4", "^a", "1.2-rc.1", "1.*-rc", "=>1", "1.2.3-",
+        ] {
+            assert!(Comparator::parse(invalid).is_none(), "{invalid}");
+        }
+        assert!(PackageSpec::parse("serde@^1, nope").is_err());
+        assert!(PackageSpec::parse("svn:serde").is_err());
+        assert!(PackageSpec::parse("registry:serde*@>=1.0.100, <2").is_ok());
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    fs,
+    io::{self, Write},
+    iter,
+    path::{Path, PathBuf},
+};
+
+use crate::{cli::MatchMode, paths::normalize, tags::Kind, AnyError};
+
+/// An entry of a ctags file.
+pub struct Entry<'t> {
+    pub name: &'t str,
+    /// Path of the file, relative to the tags file when not absolute.
+    pub file: &'t str,
+    pub kind: &'t str,
+    pub line: &'t str,
+    pub column: &'t str,
+    pub qualified: &'t str,
+    /// Name and version of the package, `-` when the entry does not say.
+    pub package: &'t str,
+}
+
+impl<'t> Entry<'t> {
+    fn parse(line: &'t str) -> Option<Self> {
+        let (head, fields) = line.rsplit_once(";\"\t")?;
+        let mut head = head.split('\t');
+        let (name, file) = (head.next()?, head.next()?);
+
+        let mut entry = Entry {
+            name,
+            file,
+            kind: "",
+            line: "",
+            column: "1",
+            qualified: name,
+            package: "-",
+        };
+        for field in fields.split('\t') {
+            match field.split_once(':') {
+                Some(("kind", kind)) => entry.kind = kind,
+                Some(("line", line)) => entry.line = line,
+                Some(("column", column)) => entry.column = column,
+                Some(("qualified", qualified)) => entry.qualified = qualified,
+                Some(("crate", package)) => entry.package = package,
+                None => entry.kind = field,
+                _ => (),
+            }
+        }
+        Some(entry)
+    }
+
+    /// Name of the kind, which is written as a letter.
+    pub fn kind_name(&self) -> &'t str {
+        self.kind().map_or(self.kind, |kind| kind.name())
+    }
+
+    pub fn kind(&self) -> Option<Kind> {
+        let mut letters = self.kind.chars();
+        match (letters.next().and_then(Kind::from_letter), letters.next()) {
+            (Some(kind), None) => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the characters of `pattern` appear in `text` in order, ignoring
+/// case.
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|c| text.any(|t| t == c))
+}
+
+/// Whether `entry` matches `pattern`: its name, or its qualified path when
+/// the pattern contains `::`. Qualified patterns match whole trailing path
+/// segments, so `de::Error` finds `serde::de::Error`.
+fn matches(pattern: &str, mode: MatchMode, entry: &Entry) -> bool {
+    if !pattern.contains("::") {
+        let name = entry.name;
+        return match mode {
+            MatchMode::Exact => name == pattern,
+            MatchMode::Prefix => name.starts_with(pattern),
+            MatchMode::Substring => name.contains(pattern),
+            MatchMode::Fuzzy => fuzzy_match(pattern, name),
+        };
+    }
+
+    let qualified = entry.qualified;
+    let mut segments = iter::once(0).chain(qualified.match_indices("::").map(|(i, _)| i + 2));
+    match mode {
+        MatchMode::Exact => segments.any(|i| &qualified[i..] == pattern),
+        MatchMode::Prefix => segments.any(|i| qualified[i..].starts_with(pattern)),
+        MatchMode::Substring => qualified.contains(pattern),
+        MatchMode::Fuzzy => fuzzy_match(pattern, qualified),
+    }
+}
+
+/// Offset of the first line of the sorted `tags` whose name does not come
+/// before `key`, bisecting the file by bytes.
+fn lower_bound(tags: &str, key: &str) -> usize {
+    let bytes = tags.as_bytes();
+    let (mut lo, mut hi) = (0, tags.len());
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let start = bytes[..mid]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let end = bytes[mid..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(tags.len(), |i| mid + i + 1);
+        let name = tags[start..end]
+            .split(['\t', '\n'])
+            .next()
+            .unwrap_or_default();
+        if name < key {
+            lo = end;
+        } else {
+            hi = start;
+        }
+    }
+    lo
+}
+
+/// Visit the entries of the ctags file `tags` matching `pattern` until
+/// `visit` returns `false`.
+///
+/// Exact and prefix matches only read the entries sharing the last segment
+/// of the pattern, found by binary search; the other modes read them all.
+pub fn search<'t>(
+    tags: &'t str,
+    pattern: &str,
+    mode: MatchMode,
+    mut visit: impl FnMut(Entry<'t>) -> bool,
+) {
+    let key = pattern.rsplit("::").next().unwrap_or(pattern);
+    let lines = match mode {
+        MatchMode::Exact | MatchMode::Prefix => &tags[lower_bound(tags, key)..],
+        MatchMode::Substring | MatchMode::Fuzzy => tags,
+    };
+
+    for line in lines.lines() {
+        let Some(entry) = Entry::parse(line) else {
+            continue;
+        };
+        let past = match mode {
+            MatchMode::Exact => entry.name != key,
+            MatchMode::Prefix => !entry.name.starts_with(key),
+            MatchMode::Substring | MatchMode::Fuzzy => false,
+        };
+        if past {
+            break;
+        }
+        // entries named by their path, as added by `--qualified`, are found
+        // by their original name already
+        if entry.name.contains("::") || !matches(pattern, mode, &entry) {
+            continue;
+        }
+        if !visit(entry) {
+            break;
+        }
+    }
+}
+
+/// Print the entries of the ctags `files` matching `pattern`, one per line
+/// as `file:line:column kind qualified_name crate@version`, returning
+/// whether there were any.
+pub fn find(pattern: &str, mode: MatchMode, files: &[PathBuf]) -> Result<bool, AnyError> {
+    let mut w = io::stdout().lock();
+    let mut found = false;
+
+    for file in files {
+        let tags = fs::read_to_string(file).map_err(|err| format!("{}: {err}", file.display()))?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+
+        let mut result = Ok(());
+        search(&tags, pattern, mode, |entry| {
+            found = true;
+            result = writeln!(
+                w,
+                "{}:{}:{} {} {} {}",
+                normalize(&dir.join(entry.file)).display(),
+                entry.line,
+                entry.column,
+                entry.kind_name(),
+                entry.qualified,
+                entry.package,
+            );
+            result.is_ok()
+        });
+        match result {
+            // the reader, such as `head`, has seen enough
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(found),
+            result => result?,
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAGS: &str = "\
+!_TAG_FILE_FORMAT\t2\t/extended format/
+!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/
+Error\tsrc/code.rs\t/^pub struct Error;$/;\"\ts\tline:1\tcolumn:12\tqualified:codec::code::Error\tcrate:codec@1.0.0
+Error\tsrc/de.rs\t/^pub trait Error {$/;\"\tt\tline:2\tcolumn:11\tqualified:serde::de::Error\tcrate:serde@1.0.0
+ErrorKind\tsrc/error.rs\t/^pub enum ErrorKind {$/;\"\tg\tline:3\tcolumn:10\tqualified:std::io::ErrorKind\tcrate:std@-
+Errors\tsrc/lib.rs\t/^pub struct Errors;$/;\"\ts\tline:4\tcolumn:12\tqualified:errs::Errors\tcrate:errs@0.1.0
+Errur\tsrc/lib.rs\t/^pub fn Errur() {}$/;\"\tf\tline:5\tcolumn:8\tqualified:errs::Errur\tcrate:errs@0.1.0
+serde::de::Error\tsrc/de.rs\t/^pub trait Error {$/;\"\tt\tline:2\tcolumn:11\tqualified:serde::de::Error\tcrate:serde@1.0.0
+zeta\tsrc/z.rs\t/^fn zeta() {}$/;\"\tf\tline:9\tcolumn:4\tqualified:z::zeta\tcrate:z@0.1.0
+";
+
+    fn found(pattern: &str, mode: MatchMode) -> Vec<&'static str> {
+        let mut found = vec![];
+        search(TAGS, pattern, mode, |entry| {
+            found.push(entry.qualified);
+            true
+        });
+        found
+    }
+
+    fn name_at(offset: usize) -> &'static str {
+        TAGS[offset..].split('\t').next().unwrap()
+    }
+
+    #[test]
+    fn bounds() {
+        assert_eq!(lower_bound(TAGS, ""), 0);
+        assert_eq!(
+            lower_bound(TAGS, "!_TAG_FILE_SORTED"),
+            TAGS.find("!_TAG_FILE_S").unwrap()
+        );
+        assert_eq!(name_at(lower_bound(TAGS, "A")), "Error");
+        assert_eq!(lower_bound(TAGS, "Error"), TAGS.find("Error").unwrap());
+        assert_eq!(name_at(lower_bound(TAGS, "ErrorL")), "Errors");
+        assert_eq!(name_at(lower_bound(TAGS, "y")), "zeta");
+        assert_eq!(lower_bound(TAGS, "zz"), TAGS.len());
+        assert_eq!(lower_bound("", "a"), 0);
+    }
+
+    #[test]
+    fn modes() {
+        use MatchMode::*;
+        assert_eq!(
+            found("Error", Exact),
+            ["codec::code::Error", "serde::de::Error"]
+        );
+        assert_eq!(
+            found("Error", Prefix),
+            [
+                "codec::code::Error",
+                "serde::de::Error",
+                "std::io::ErrorKind",
+                "errs::Errors",
+            ]
+        );
+        assert_eq!(found("zz", Prefix), [] as [&str; 0]);
+        assert_eq!(found("!_TAG", Prefix), [] as [&str; 0]);
+        assert_eq!(found("rru", Substring), ["errs::Errur"]);
+        assert_eq!(found("erkd", Fuzzy), ["std::io::ErrorKind"]);
+    }
+
+    #[test]
+    fn prefix_stops_at_first_mismatch() {
+        let mut names = vec![];
+        search(TAGS, "Errors", MatchMode::Prefix, |entry| {
+            names.push(entry.name);
+            true
+        });
+        assert_eq!(names, ["Errors"]);
+
+        // `visit` ends the search
+        let mut visited = 0;
+        search(TAGS, "Error", MatchMode::Prefix, |_| {
+            visited += 1;
+            false
+        });
+        assert_eq!(visited, 1);
+    }
+
+    #[test]
+    fn qualified_patterns() {
+        use MatchMode::*;
+        assert_eq!(found("de::Error", Exact), ["serde::de::Error"]);
+        assert_eq!(found("serde::de::Error", Exact), ["serde::de::Error"]);
+        assert_eq!(found("e::Error", Exact), [] as [&str; 0]);
+        assert_eq!(found("io::Err", Prefix), ["std::io::ErrorKind"]);
+        assert_eq!(
+            found("de::Error", Substring),
+            ["codec::code::Error", "serde::de::Error"]
+        );
+    }
+}
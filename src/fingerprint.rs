@@ -0,0 +1,196 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::{escape, fnv1a, mtime, unescape},
+    cli::Config,
+    metadata::Package,
+    write_atomic,
+};
+
+/// First line of every fingerprint, bumped whenever the layout changes.
+const HEADER: &str = "cargo-symbols fingerprint 1";
+
+/// Path of the fingerprint of the tags file at `output`, a hidden sibling.
+fn path(output: &Path) -> PathBuf {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!(".{name}.fingerprint"))
+}
+
+/// Hash of everything the tags file is generated from, except the sources of
+/// path packages: the version of cargo-symbols, the options, `Cargo.lock` and
+/// the targets of the tagged packages.
+pub fn inputs(workspace_root: &Path, packages: &[Package], config: &Config) -> u64 {
+    let options = [
+        format!(
+            "format={:?} append={} split={}",
+            config.format, config.append, config.split
+        ),
+        format!(
+            "relative={} remap={:?}",
+            config.relative, config.remap_path_prefix
+        ),
+        format!(
+            "qualified={} public-only={} priority={:?}",
+            config.qualified, config.public_only, config.priority
+        ),
+        format!(
+            "backend={:?} ctags={} {:?}",
+            config.backend, config.ctags, config.ctags_args
+        ),
+        format!(
+            "features={:?} all={} no-default={} platform={:?}",
+            config.features,
+            config.all_features,
+            config.no_default_features,
+            config.filter_platform
+        ),
+        format!(
+            "depth={:?} dev={} build={} std={}",
+            config.depth, config.dev, config.build, config.std
+        ),
+        format!(
+            "build-scripts={} examples={} tests={} benches={}",
+            config.build_scripts, config.examples, config.tests, config.benches
+        ),
+    ];
+    let mut inputs = format!("{}\n{}\n", env!("CARGO_PKG_VERSION"), options.join("\n"));
+
+    // a missing lock file hashes like an empty one
+    let lock = fs::read(workspace_root.join("Cargo.lock")).unwrap_or_default();
+    inputs += &format!("{:016x}\n", fnv1a(&lock));
+
+    for package in packages {
+        inputs += &package.id;
+        for target in &package.targets {
+            let kind = target.kind.join(",");
+            inputs += &format!(" {kind}:{}={}", target.name, target.src_path.display());
+        }
+        inputs += "\n";
+    }
+
+    fnv1a(inputs.as_bytes())
+}
+
+/// Whether the tags file at `output` exists and was generated from `inputs`
+/// and the current version of the files of its path packages.
+pub fn is_fresh(output: &Path, inputs: u64) -> bool {
+    let Ok(fingerprint) = fs::read_to_string(path(output)) else {
+        return false;
+    };
+    if !output.exists() {
+        return false;
+    }
+
+    let mut lines = fingerprint.lines();
+    if lines.next() != Some(HEADER) || lines.next() != Some(&format!("{inputs:016x}")) {
+        return false;
+    }
+
+    lines.all(|line| match line.split_once('\t') {
+        Some((modified, file)) => mtime(Path::new(&unescape(file))) == modified,
+        None => false,
+    })
+}
+
+/// Record that the tags file at `output` was generated from `inputs` and
+/// the source `files` of path packages.
+pub fn write(output: &Path, inputs: u64, files: &[PathBuf]) -> io::Result<()> {
+    write_atomic(&path(output), |w| {
+        writeln!(w, "{HEADER}")?;
+        writeln!(w, "{inputs:016x}")?;
+        for file in files {
+            writeln!(w, "{}\t{}", mtime(file), escape(&file.to_string_lossy()))?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::*;
+    use crate::metadata::Target;
+
+    #[test]
+    fn inputs_change() {
+        let dir = env::temp_dir().join(format!("cargo-symbols-fingerprint-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let packages = vec![Package {
+            id: "a 0.1.0".to_owned(),
+            targets: vec![Target {
+                name: "a".to_owned(),
+                kind: vec!["lib".to_owned()],
+                src_path: dir.join("lib.rs"),
+            }],
+            ..Package::default()
+        }];
+        let config = Config::default();
+        let hash = inputs(&dir, &packages, &config);
+        // a missing lock file is the same as an empty one
+        fs::write(dir.join("Cargo.lock"), "").unwrap();
+        assert_eq!(inputs(&dir, &packages, &config), hash);
+
+        fs::write(dir.join("Cargo.lock"), "[[package]]\n").unwrap();
+        assert_ne!(inputs(&dir, &packages, &config), hash);
+        fs::write(dir.join("Cargo.lock"), "").unwrap();
+
+        let other = Config {
+            qualified: true,
+            ..Config::default()
+        };
+        assert_ne!(inputs(&dir, &packages, &other), hash);
+        assert_ne!(inputs(&dir, &packages[..0], &config), hash);
+        let moved = vec![Package {
+            id: packages[0].id.clone(),
+            targets: vec![Target::default()],
+            ..Package::default()
+        }];
+        assert_ne!(inputs(&dir, &moved, &config), hash);
+        assert_eq!(inputs(&dir, &packages, &config), hash);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn freshness() {
+        let dir = env::temp_dir().join(format!("cargo-symbols-fresh-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("tags");
+        let source = dir.join("lib\tsrc.rs");
+        fs::write(&source, "pub fn f() {}\n").unwrap();
+        let files = vec![source.clone()];
+
+        // no tags file or fingerprint yet
+        assert!(!is_fresh(&output, 1));
+        write(&output, 1, &files).unwrap();
+        assert!(path(&output).exists());
+        assert!(!is_fresh(&output, 1));
+
+        fs::write(&output, "").unwrap();
+        assert!(is_fresh(&output, 1));
+        assert!(!is_fresh(&output, 2));
+
+        let file = fs::File::options().write(true).open(&source).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+        assert!(!is_fresh(&output, 1));
+        write(&output, 1, &files).unwrap();
+        assert!(is_fresh(&output, 1));
+
+        fs::remove_file(&source).unwrap();
+        assert!(!is_fresh(&output, 1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
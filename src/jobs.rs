@@ -0,0 +1,170 @@
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// Client side of a make style jobserver, as shared by cargo and make with
+/// their children: every job beyond the first needs a token read from the
+/// pipe, and gives it back when done.
+pub struct Jobserver {
+    read: File,
+    write: File,
+}
+
+impl Jobserver {
+    /// Connect to the jobserver advertised in `CARGO_MAKEFLAGS` or
+    /// `MAKEFLAGS`, if any.
+    pub fn from_env() -> Option<Self> {
+        let flags = env::var("CARGO_MAKEFLAGS")
+            .or_else(|_| env::var("MAKEFLAGS"))
+            .ok()?;
+        let auth = flags.split(' ').rev().find_map(|flag| {
+            flag.strip_prefix("--jobserver-auth=")
+                .or_else(|| flag.strip_prefix("--jobserver-fds="))
+        })?;
+
+        let (read, write) = match auth.strip_prefix("fifo:") {
+            Some(path) => (path.to_owned(), path.to_owned()),
+            None => {
+                let (read, write) = auth.split_once(',')?;
+                (format!("/dev/fd/{read}"), format!("/dev/fd/{write}"))
+            }
+        };
+
+        let read = OpenOptions::new().read(true).open(read).ok()?;
+        let write = OpenOptions::new().write(true).open(write).ok()?;
+        (is_fifo(&read) && is_fifo(&write)).then_some(Self { read, write })
+    }
+
+    fn acquire(&self) -> Option<u8> {
+        let mut token = [0];
+        (&self.read).read_exact(&mut token).ok()?;
+        Some(token[0])
+    }
+
+    fn release(&self, token: u8) {
+        let _ = (&self.write).write_all(&[token]);
+    }
+}
+
+/// Whether `file` is a pipe, and not some other file that happens to have
+/// the advertised descriptor.
+#[cfg(unix)]
+fn is_fifo(file: &File) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    file.metadata().is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_: &File) -> bool {
+    false
+}
+
+/// Default number of jobs: the available parallelism.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Apply `f` to every item on up to `jobs` threads, returning the results in
+/// the order of `items` regardless of completion order.
+pub fn run<T, R, F>(items: &[T], jobs: usize, jobserver: Option<&Jobserver>, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let jobs = jobs.clamp(1, items.len().max(1));
+
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|worker| {
+                let (next, f) = (&next, &f);
+                scope.spawn(move || {
+                    let mut results = vec![];
+                    loop {
+                        // the first worker runs on the token this process
+                        // implicitly holds, and is left the remaining items
+                        // when the jobserver fails to give the others one
+                        let token = match (worker, jobserver) {
+                            (0, _) | (_, None) => None,
+                            (_, Some(jobserver)) => match jobserver.acquire() {
+                                Some(token) => Some(token),
+                                None => break,
+                            },
+                        };
+
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let item = items.get(i);
+                        if let Some(item) = item {
+                            results.push((i, f(item)));
+                        }
+                        if let (Some(jobserver), Some(token)) = (jobserver, token) {
+                            jobserver.release(token);
+                        }
+                        if item.is_none() {
+                            break;
+                        }
+                    }
+                    results
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("worker panicked"))
+            .collect()
+    });
+
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn results_in_order() {
+        let items: Vec<u64> = (0..16).collect();
+        // later items finish first
+        let slow = |&n: &u64| {
+            thread::sleep(Duration::from_millis(16 - n));
+            n * 10
+        };
+        let expected: Vec<u64> = items.iter().map(|n| n * 10).collect();
+        for jobs in [1, 4, 64] {
+            assert_eq!(run(&items, jobs, None, slow), expected);
+        }
+        assert_eq!(run(&items, 0, None, |n| *n), items);
+        assert!(run(&[] as &[u64], 4, None, |n| *n).is_empty());
+    }
+
+    #[test]
+    fn failing_jobserver() {
+        let file = env::temp_dir().join(format!("cargo-symbols-jobs-{}", std::process::id()));
+        File::create(&file).unwrap();
+        // every read of a token fails, as once the jobserver is gone
+        let jobserver = Jobserver {
+            read: File::open(&file).unwrap(),
+            write: OpenOptions::new().append(true).open(&file).unwrap(),
+        };
+
+        let items: Vec<usize> = (0..8).collect();
+        let results = run(&items, 4, Some(&jobserver), |&n| {
+            (n, thread::current().id())
+        });
+        std::fs::remove_file(&file).unwrap();
+
+        let order: Vec<_> = results.iter().map(|&(n, _)| n).collect();
+        assert_eq!(order, items);
+        // all run by the worker on the implicit token
+        assert!(results.iter().all(|(_, id)| *id == results[0].1));
+    }
+}
@@ -0,0 +1,490 @@
+use std::{error::Error, fmt};
+
+/// A JSON value.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    List(Vec<Json>),
+    /// Entries in document order.
+    Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Value of `key`, when `self` is an object containing it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Obj(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Items of a list, or nothing for any other value.
+    pub fn items(&self) -> &[Json] {
+        match self {
+            Json::List(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+/// Write `s` as a JSON string literal.
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Compact JSON text, on a single line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            // JSON has no infinities or NaN
+            Json::Number(n) if !n.is_finite() => f.write_str("null"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::Str(s) => write_string(f, s),
+            Json::List(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            Json::Obj(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for JsonError {}
+
+/// Nesting deeper than this is rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 512;
+
+struct Parser<'src> {
+    json: &'src str,
+    i: usize,
+    depth: usize,
+}
+
+impl<'src> Parser<'src> {
+    fn error(&self, message: impl Into<String>) -> JsonError {
+        let before = &self.json[..self.i];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        JsonError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.i).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.i += 1;
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(c) if c == b => {
+                self.i += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.error(format!("expected `{}`", b as char))),
+            None => Err(self.error(format!("expected `{}`, found end of input", b as char))),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        if self.json[self.i..].starts_with(word) {
+            self.i += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::list),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("expected a value, found end of input")),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Json, JsonError>,
+    ) -> Result<Json, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'{')?;
+        let mut entries = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.i += 1;
+            return Ok(Json::Obj(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value = self.value()?;
+            entries.push((key, value));
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.i += 1,
+                Some(b'}') => {
+                    self.i += 1;
+                    return Ok(Json::Obj(entries));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn list(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'[')?;
+        let mut items = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.i += 1;
+            return Ok(Json::List(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.i += 1,
+                Some(b']') => {
+                    self.i += 1;
+                    return Ok(Json::List(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.i;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.i += 1;
+        }
+        self.i - start
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.i;
+        if self.peek() == Some(b'-') {
+            self.i += 1;
+        }
+
+        match self.peek() {
+            Some(b'0') => self.i += 1,
+            Some(b'1'..=b'9') => {
+                self.digits();
+            }
+            _ => return Err(self.error("expected a digit")),
+        }
+
+        if self.peek() == Some(b'.') {
+            self.i += 1;
+            if self.digits() == 0 {
+                return Err(self.error("expected a digit after the decimal point"));
+            }
+        }
+
+        if let Some(b'e' | b'E') = self.peek() {
+            self.i += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.i += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("expected a digit in the exponent"));
+            }
+        }
+
+        let number = self.json[start..self.i]
+            .parse()
+            .expect("valid float syntax");
+        Ok(Json::Number(number))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let hex = self.json.get(self.i..self.i + 4).unwrap_or("");
+        match (hex.len(), u32::from_str_radix(hex, 16)) {
+            (4, Ok(code)) if hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                self.i += 4;
+                Ok(code)
+            }
+            _ => Err(self.error("expected 4 hex digits")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut s = String::new();
+
+        loop {
+            // copy everything up to the next quote, escape or control character at once
+            let rest = &self.json[self.i..];
+            let end = rest
+                .find(|c: char| c == '"' || c == '\\' || c < ' ')
+                .unwrap_or(rest.len());
+            s.push_str(&rest[..end]);
+            self.i += end;
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.i += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    self.i += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.i += 1;
+                            let c = self.unicode_escape()?;
+                            s.push(c);
+                            continue;
+                        }
+                        Some(_) => return Err(self.error("invalid escape")),
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    self.i += 1;
+                    s.push(escaped);
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape, `i` being after the `u`. Lone
+    /// surrogates become U+FFFD.
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+
+        if !self.json[self.i..].starts_with("\\u") {
+            return Ok(char::REPLACEMENT_CHARACTER);
+        }
+        let before = self.i;
+        self.i += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            // not a pair, the second escape stands on its own
+            self.i = before;
+            return Ok(char::REPLACEMENT_CHARACTER);
+        }
+
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+/// Parse a complete JSON document.
+pub fn parse(json: &str) -> Result<Json, JsonError> {
+    let mut parser = Parser {
+        json,
+        i: 0,
+        depth: 0,
+    };
+
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.i != json.len() {
+        return Err(parser.error("trailing characters after the value"));
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_at(json: &str) -> (usize, usize) {
+        let err = parse(json).unwrap_err();
+        (err.line, err.column)
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            parse(r#" {"a": [1, -2.5, true, null], "b": {}, "c": ""} "#).unwrap(),
+            Json::Obj(vec![
+                (
+                    "a".to_owned(),
+                    Json::List(vec![
+                        Json::Number(1.0),
+                        Json::Number(-2.5),
+                        Json::Bool(true),
+                        Json::Null,
+                    ])
+                ),
+                ("b".to_owned(), Json::Obj(vec![])),
+                ("c".to_owned(), Json::Str(String::new())),
+            ])
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let s = |json| parse(json).unwrap().as_str().unwrap().to_owned();
+        assert_eq!(s(r#""\"\\\/\b\f\n\r\t""#), "\"\\/\u{8}\u{c}\n\r\t");
+        assert_eq!(s(r#""é中""#), "é中");
+        assert_eq!(s(r#""\u00e9\u4E2D""#), "é中");
+        assert_eq!(s(r#""\ud83d\ude00""#), "😀");
+        // lone surrogates
+        assert_eq!(s(r#""\ud83d""#), "\u{fffd}");
+        assert_eq!(s(r#""\ud83dx""#), "\u{fffd}x");
+        assert_eq!(s(r#""\ude00""#), "\u{fffd}");
+        assert_eq!(s(r#""\ud83d\u0041""#), "\u{fffd}A");
+        assert_eq!(s(r#""\ud83dA""#), "\u{fffd}A");
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12""#).is_err());
+        assert!(parse(r#""\u+123""#).is_err());
+        assert!(parse("\"a\nb\"").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        let n = |json| parse(json).unwrap().as_number().unwrap();
+        assert_eq!(n("-0"), 0.0);
+        assert!(n("-0").is_sign_negative());
+        assert_eq!(n("1e5"), 1e5);
+        assert_eq!(n("1.5E-2"), 0.015);
+        assert_eq!(n("0.25"), 0.25);
+        for invalid in ["01", "-", "1.", ".5", "1e", "+1", "0x10", "1e+"] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(error_at(""), (1, 1));
+        assert_eq!(error_at("{\n  \"a\": [1,\n"), (3, 1));
+        assert_eq!(error_at("{\"a\""), (1, 5));
+        assert_eq!(error_at("\"abc"), (1, 5));
+        assert_eq!(error_at("\"é\\"), (1, 4));
+        assert_eq!(error_at("[\"\\u12"), (1, 5));
+        assert_eq!(error_at("tru"), (1, 1));
+        assert_eq!(error_at("-"), (1, 2));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(error_at("[1,]"), (1, 4));
+        assert_eq!(error_at("{\"a\" 1}"), (1, 6));
+        assert_eq!(error_at("{1: 2}"), (1, 2));
+        assert_eq!(error_at("[1] x"), (1, 5));
+        assert_eq!(error_at("\n  nul"), (2, 3));
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        assert_eq!(parse(&deep).unwrap_err().message, "nesting too deep");
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = r#"{"s":"a\"b\\c\n\u0001é","n":[-1.5,0,1e+21],"o":{"t":true}}"#;
+        let value = parse(json).unwrap();
+        assert_eq!(parse(&value.to_string()).unwrap(), value);
+        assert_eq!(Json::Number(f64::NAN).to_string(), "null");
+    }
+}
@@ -0,0 +1,562 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::mtime,
+    cli::{Config, MatchMode, Verbosity},
+    find::{self, Entry},
+    json::{self, Json},
+    paths::normalize,
+    tags::Kind,
+    AnyError,
+};
+
+/// Most symbols answered to `workspace/symbol`; editors ask again as the
+/// query grows, and an empty query would otherwise send the whole file.
+const MAX_SYMBOLS: usize = 1000;
+
+// error codes of JSON-RPC and the language server protocol
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const INTERNAL_ERROR: i32 = -32603;
+const SERVER_NOT_INITIALIZED: i32 = -32002;
+
+fn object(entries: Vec<(&str, Json)>) -> Json {
+    Json::Obj(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect(),
+    )
+}
+
+/// Body of the next message, framed by a `Content-Length` header, or `None`
+/// when the client closed the stream.
+fn read_message(r: &mut impl BufRead) -> Result<Option<String>, AnyError> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+
+    let length = length.ok_or("message without a Content-Length header")?;
+    let mut body = vec![0; length];
+    r.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8(body)?))
+}
+
+fn write_message(w: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    w.flush()
+}
+
+/// `file://` URI of the absolute `path`.
+fn file_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for &b in path.to_string_lossy().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            _ => uri += &format!("%{b:02X}"),
+        }
+    }
+    uri
+}
+
+/// Path of a `file://` URI.
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let decoded = match encoded[i] {
+            b'%' => encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(b) => {
+                path.push(b);
+                i += 3;
+            }
+            None => {
+                path.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(path).ok()?))
+}
+
+/// `SymbolKind` of the protocol closest to `kind`.
+fn symbol_kind(kind: Option<Kind>) -> f64 {
+    use Kind::*;
+    match kind {
+        Some(Module) => 2.0,
+        Some(Impl) => 5.0,
+        Some(Method) => 6.0,
+        Some(Field) => 8.0,
+        Some(Enum) => 10.0,
+        Some(Trait) => 11.0,
+        Some(Function | Macro) => 12.0,
+        Some(Static) | None => 13.0,
+        Some(Const) => 14.0,
+        Some(Variant) => 22.0,
+        Some(Struct | Union) => 23.0,
+        Some(Type) => 26.0,
+    }
+}
+
+/// `Location` of `entry`, from the tags file in `dir`.
+fn location(dir: &Path, entry: &Entry) -> Json {
+    let number = |n: usize| Json::Number(n as f64);
+    let line = entry.line.parse::<usize>().unwrap_or(1).saturating_sub(1);
+    // columns count chars, which are UTF-16 units outside of rare scripts
+    let character = entry.column.parse::<usize>().unwrap_or(1).saturating_sub(1);
+    let position = |character| {
+        object(vec![
+            ("line", number(line)),
+            ("character", number(character)),
+        ])
+    };
+    let range = object(vec![
+        ("start", position(character)),
+        (
+            "end",
+            position(character + entry.name.encode_utf16().count()),
+        ),
+    ]);
+
+    let path = normalize(&dir.join(entry.file));
+    object(vec![("uri", Json::Str(file_uri(&path))), ("range", range)])
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Path under the cursor at `line` and UTF-16 offset `character` of `text`:
+/// the identifier there, with the segments before it such as `de::Error`.
+/// Leading `crate`, `self`, `super` and `Self` say nothing about where
+/// the item is and are left out.
+fn path_at(text: &str, line: usize, character: usize) -> Option<String> {
+    let chars: Vec<char> = text.lines().nth(line)?.chars().collect();
+
+    let mut units = 0;
+    let mut cursor = chars.len();
+    for (i, c) in chars.iter().enumerate() {
+        if units >= character {
+            cursor = i;
+            break;
+        }
+        units += c.len_utf16();
+    }
+    // the cursor may be right after the identifier
+    if !chars.get(cursor).is_some_and(|&c| is_ident(c)) {
+        cursor = cursor.checked_sub(1).filter(|&i| is_ident(chars[i]))?;
+    }
+
+    let mut end = cursor;
+    while end < chars.len() && is_ident(chars[end]) {
+        end += 1;
+    }
+    let mut start = cursor;
+    loop {
+        while start > 0 && is_ident(chars[start - 1]) {
+            start -= 1;
+        }
+        match chars[..start] {
+            [.., c, ':', ':'] if is_ident(c) => start -= 2,
+            _ => break,
+        }
+    }
+
+    let path: String = chars[start..end].iter().collect();
+    let segments: Vec<_> = path.split("::").collect();
+    let first = segments
+        .iter()
+        .rposition(|&segment| matches!(segment, "crate" | "self" | "super" | "Self"))
+        .map_or(0, |i| i + 1);
+    let path = segments.get(first..).filter(|rest| !rest.is_empty())?;
+    Some(path.join("::"))
+}
+
+/// Failure of a request, answered with its code.
+struct RequestError(i32, String);
+
+impl From<AnyError> for RequestError {
+    fn from(err: AnyError) -> Self {
+        RequestError(INTERNAL_ERROR, err.to_string())
+    }
+}
+
+fn invalid_params() -> RequestError {
+    RequestError(INVALID_PARAMS, "invalid params".to_owned())
+}
+
+/// A tags file read into memory, with the modification time it had then.
+struct TagsFile {
+    path: PathBuf,
+    modified: String,
+    contents: String,
+}
+
+struct Server {
+    tags: Vec<TagsFile>,
+    mode: MatchMode,
+    /// Text of the documents opened in the editor, by URI.
+    documents: HashMap<String, String>,
+    initialized: bool,
+    shut_down: bool,
+}
+
+impl Server {
+    /// Read the tags files again when they were written since they last
+    /// were, by a run of cargo symbols in another terminal or the editor.
+    fn reload(&mut self) -> Result<(), AnyError> {
+        for file in &mut self.tags {
+            let modified = mtime(&file.path);
+            if modified != file.modified {
+                file.contents = fs::read_to_string(&file.path)
+                    .map_err(|err| format!("{}: {err}", file.path.display()))?;
+                file.modified = modified;
+            }
+        }
+        Ok(())
+    }
+
+    /// `SymbolInformation` of the symbols matching `query`.
+    fn workspace_symbol(&mut self, params: &Json) -> Result<Json, RequestError> {
+        let query = params
+            .get("query")
+            .and_then(Json::as_str)
+            .ok_or_else(invalid_params)?;
+        self.reload()?;
+
+        let mut symbols = vec![];
+        for file in &self.tags {
+            if symbols.len() >= MAX_SYMBOLS {
+                break;
+            }
+            let dir = file.path.parent().unwrap_or(Path::new(""));
+            find::search(&file.contents, query, self.mode, |entry| {
+                let container = entry
+                    .qualified
+                    .rsplit_once("::")
+                    .map_or(Json::Null, |(scope, _)| Json::Str(scope.to_owned()));
+                symbols.push(object(vec![
+                    ("name", Json::Str(entry.name.to_owned())),
+                    ("kind", Json::Number(symbol_kind(entry.kind()))),
+                    ("location", location(dir, &entry)),
+                    ("containerName", container),
+                ]));
+                symbols.len() < MAX_SYMBOLS
+            });
+        }
+        Ok(Json::List(symbols))
+    }
+
+    /// `Location` of the items named by the path under the cursor.
+    fn definition(&mut self, params: &Json) -> Result<Json, RequestError> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(Json::as_str)
+            .ok_or_else(invalid_params)?;
+        let position = params.get("position").ok_or_else(invalid_params)?;
+        let number = |key| {
+            position
+                .get(key)
+                .and_then(Json::as_number)
+                .map(|n| n as usize)
+                .ok_or_else(invalid_params)
+        };
+        let (line, character) = (number("line")?, number("character")?);
+
+        // documents the editor did not open are read as they are on disk
+        let text = match self.documents.get(uri) {
+            Some(text) => text.clone(),
+            None => uri_path(uri)
+                .and_then(|path| fs::read_to_string(path).ok())
+                .unwrap_or_default(),
+        };
+        let Some(path) = path_at(&text, line, character) else {
+            return Ok(Json::Null);
+        };
+        self.reload()?;
+
+        // items are often reexported elsewhere than they are defined, so all
+        // those of the name are candidates, and only the best are answered:
+        // those of the crate the path starts with, then those ending with
+        // the whole path, so `regex::Regex` leads to regex rather than to
+        // `regex_automata::meta::regex::Regex`
+        let name = path.rsplit("::").next().unwrap_or(&path);
+        let first = path.split("::").next().unwrap_or(&path);
+        let suffix = format!("::{path}");
+        let mut candidates = vec![];
+        for file in &self.tags {
+            let dir = file.path.parent().unwrap_or(Path::new(""));
+            find::search(&file.contents, name, MatchMode::Exact, |entry| {
+                let other_crate = entry.qualified.split("::").next() != Some(first);
+                let other_path = entry.qualified != path && !entry.qualified.ends_with(&suffix);
+                // an impl block of a type is not where the type is defined
+                let is_impl = entry.kind() == Some(Kind::Impl);
+                let rank = (other_crate, other_path, is_impl);
+                candidates.push((rank, location(dir, &entry)));
+                true
+            });
+        }
+
+        let best = candidates.iter().map(|(rank, _)| *rank).min();
+        let locations = candidates
+            .into_iter()
+            .filter(|(rank, _)| Some(*rank) == best)
+            .map(|(_, location)| location)
+            .collect();
+        Ok(Json::List(locations))
+    }
+
+    /// Result of the request `method`.
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, RequestError> {
+        if self.shut_down {
+            return Err(RequestError(
+                INVALID_REQUEST,
+                "the server is shut down".to_owned(),
+            ));
+        }
+        if !self.initialized && method != "initialize" {
+            return Err(RequestError(
+                SERVER_NOT_INITIALIZED,
+                "the server is not initialized".to_owned(),
+            ));
+        }
+
+        match method {
+            "initialize" => {
+                self.initialized = true;
+                let capabilities = object(vec![
+                    // the whole text is sent on every change
+                    ("textDocumentSync", Json::Number(1.0)),
+                    ("workspaceSymbolProvider", Json::Bool(true)),
+                    ("definitionProvider", Json::Bool(true)),
+                ]);
+                let server_info = object(vec![
+                    ("name", Json::Str(env!("CARGO_PKG_NAME").to_owned())),
+                    ("version", Json::Str(env!("CARGO_PKG_VERSION").to_owned())),
+                ]);
+                Ok(object(vec![
+                    ("capabilities", capabilities),
+                    ("serverInfo", server_info),
+                ]))
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "workspace/symbol" => self.workspace_symbol(params),
+            "textDocument/definition" => self.definition(params),
+            _ => Err(RequestError(
+                METHOD_NOT_FOUND,
+                format!("unsupported method `{method}`"),
+            )),
+        }
+    }
+
+    /// Keep the text of the open documents, as the editor edits them.
+    fn notification(&mut self, method: &str, params: &Json) {
+        let document = params.get("textDocument");
+        let Some(uri) = document.and_then(|document| document.get("uri")) else {
+            return;
+        };
+        let Some(uri) = uri.as_str().map(str::to_owned) else {
+            return;
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                if let Some(text) = document.and_then(|d| d.get("text")).and_then(Json::as_str) {
+                    self.documents.insert(uri, text.to_owned());
+                }
+            }
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").map_or(&[][..], Json::items);
+                let text = changes.last().and_then(|change| change.get("text"));
+                if let Some(text) = text.and_then(Json::as_str) {
+                    self.documents.insert(uri, text.to_owned());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Answer the requests of an editor on stdin and stdout, from the ctags
+/// `files`, until it asks the server to exit. Returns the exit code, which
+/// tells whether it was shut down first.
+pub fn serve(files: Vec<PathBuf>, config: &Config) -> Result<i32, AnyError> {
+    let tags = files
+        .into_iter()
+        .map(|path| TagsFile {
+            path,
+            modified: String::new(),
+            contents: String::new(),
+        })
+        .collect();
+    let mut server = Server {
+        tags,
+        mode: config.match_mode,
+        documents: HashMap::new(),
+        initialized: false,
+        shut_down: false,
+    };
+    server.reload()?;
+
+    let mut r = io::stdin().lock();
+    let mut w = io::stdout().lock();
+    while let Some(body) = read_message(&mut r)? {
+        let message = match json::parse(&body) {
+            Ok(message) => message,
+            Err(err) => {
+                let error = object(vec![
+                    ("code", Json::Number(PARSE_ERROR.into())),
+                    ("message", Json::Str(err.to_string())),
+                ]);
+                let response = object(vec![
+                    ("jsonrpc", Json::Str("2.0".to_owned())),
+                    ("id", Json::Null),
+                    ("error", error),
+                ]);
+                write_message(&mut w, &response)?;
+                continue;
+            }
+        };
+
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        if config.verbosity >= Verbosity::Verbose {
+            eprintln!("received {method}");
+        }
+
+        // notifications have no id and get no response
+        let Some(id) = message.get("id") else {
+            if method == "exit" {
+                return Ok(if server.shut_down { 0 } else { 1 });
+            }
+            server.notification(method, params);
+            continue;
+        };
+
+        let outcome = match server.request(method, params) {
+            Ok(result) => ("result", result),
+            Err(RequestError(code, message)) => (
+                "error",
+                object(vec![
+                    ("code", Json::Number(code.into())),
+                    ("message", Json::Str(message)),
+                ]),
+            ),
+        };
+        let response = object(vec![
+            ("jsonrpc", Json::Str("2.0".to_owned())),
+            ("id", id.clone()),
+            outcome,
+        ]);
+        write_message(&mut w, &response)?;
+    }
+
+    // the editor went away without asking
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_at_cursor() {
+        let text =
+            "use serde::de::Error;\nlet x = crate::cli::Config::default();\n\tSelf::new(\"🦀\", ü)";
+        assert_eq!(path_at(text, 0, 4).as_deref(), Some("serde"));
+        assert_eq!(path_at(text, 0, 16).as_deref(), Some("serde::de::Error"));
+        // right after the identifier
+        assert_eq!(path_at(text, 0, 20).as_deref(), Some("serde::de::Error"));
+        assert_eq!(path_at(text, 0, 3).as_deref(), Some("use"));
+        assert_eq!(path_at(text, 1, 21).as_deref(), Some("cli::Config"));
+        assert_eq!(
+            path_at(text, 1, 30).as_deref(),
+            Some("cli::Config::default")
+        );
+        assert_eq!(path_at(text, 1, 10), None);
+        assert_eq!(path_at(text, 2, 8).as_deref(), Some("new"));
+        // UTF-16 offsets after non-ASCII characters
+        assert_eq!(path_at(text, 2, 16), None);
+        assert_eq!(path_at(text, 2, 17).as_deref(), Some("ü"));
+        assert_eq!(path_at(text, 3, 0), None);
+    }
+
+    #[test]
+    fn uris() {
+        let path = Path::new("/home/me/my crate/src/lib.rs");
+        assert_eq!(file_uri(path), "file:///home/me/my%20crate/src/lib.rs");
+        for path in [
+            "/a/b.rs",
+            "/home/me/my crate/src/lib.rs",
+            "/tmp/ünï%cödé/#[x].rs",
+        ] {
+            assert_eq!(
+                uri_path(&file_uri(Path::new(path))).unwrap(),
+                Path::new(path)
+            );
+        }
+        assert_eq!(uri_path("file:///a%2"), Some(PathBuf::from("/a%2")));
+        assert_eq!(uri_path("untitled:Untitled-1"), None);
+    }
+
+    #[test]
+    fn messages() {
+        let stream = "Content-Length: 7\r\n\
+                      Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n\
+                      \r\n\
+                      {\"a\":1}\
+                      content-length:4\r\n\r\nnull";
+        let mut r = stream.as_bytes();
+        assert_eq!(read_message(&mut r).unwrap().as_deref(), Some("{\"a\":1}"));
+        assert_eq!(read_message(&mut r).unwrap().as_deref(), Some("null"));
+        assert_eq!(read_message(&mut r).unwrap(), None);
+
+        let mut w = vec![];
+        write_message(&mut w, &Json::Str("é".to_owned())).unwrap();
+        assert_eq!(w, b"Content-Length: 4\r\n\r\n\"\xc3\xa9\"");
+        assert_eq!(
+            read_message(&mut w.as_slice()).unwrap().as_deref(),
+            Some("\"é\"")
+        );
+
+        assert!(read_message(&mut "Content-Type: x\r\n\r\n{}".as_bytes()).is_err());
+        assert!(read_message(&mut "Content-Length: 10\r\n\r\n{}".as_bytes()).is_err());
+    }
+}
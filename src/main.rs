@@ -1,176 +1,411 @@
+mod cache;
+mod cli;
+mod ctags;
+mod filter;
+mod find;
+mod fingerprint;
+mod jobs;
+mod json;
+mod lsp;
+mod metadata;
+mod native;
+mod paths;
+mod public;
+mod split;
+mod sqlite;
+mod tags;
+
 use std::{
+    collections::HashSet,
     env,
     error::Error,
-    iter,
-    process::{exit, Command},
-    str,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{self, exit, Command, Stdio},
+    thread,
+};
+
+use cache::Cache;
+use cli::{Action, Backend, Config, MessageFormat, Subcommand, Verbosity};
+use jobs::Jobserver;
+use json::Json;
+use metadata::{
+    get_dependencies, std_packages, target_directory, use_cargo_metadata, workspace_root, Package,
 };
+use paths::PathMap;
+use tags::{Format, Tag};
 
 type AnyError = Box<dyn Error>;
 
-struct JsonScanner<'src> {
-    cs: iter::Peekable<str::Chars<'src>>,
-    i: usize,
-    json: &'src str,
+/// Tags of the targets of `package` selected by `config`, from the `cache`
+/// when they are in it, and the files they come from.
+fn tag_package(
+    package: &Package,
+    cache: Option<&Cache>,
+    config: &Config,
+) -> Result<(Vec<Tag>, Vec<PathBuf>), AnyError> {
+    if let Some(cached) = cache.and_then(|cache| cache.load(package)) {
+        if config.verbosity >= Verbosity::Verbose {
+            eprintln!("using cached tags of {} {}", package.name, package.version);
+        }
+        return Ok(cached);
+    }
+
+    if config.verbosity >= Verbosity::Normal {
+        eprintln!("tagging {} {}", package.name, package.version);
+    }
+
+    // targets of a package often share modules, tag those once
+    let mut visited = HashSet::new();
+    let roots = package
+        .targets
+        .iter()
+        .filter(|target| config.tags_target(&target.kind));
+
+    let public_only = public::applies(config.public_only, package.tier);
+
+    let mut tags = vec![];
+    for target in roots {
+        let (root, name) = (&target.src_path, &target.crate_name());
+        let (mut target_tags, exports) = match config.backend {
+            Backend::Native => native::tag_crate(root, name, &mut visited)?,
+            Backend::Ctags => {
+                let (files, exports) = native::module_tree(root, name, &mut visited)?;
+                (ctags::tag_files(&files, config)?, exports)
+            }
+        };
+        if public_only {
+            public::retain_reachable(&mut target_tags, name, &exports);
+        }
+        tags.extend(target_tags);
+    }
+
+    // a cache that cannot be written only costs time on the next run
+    if let Some(Err(err)) = cache.map(|cache| cache.store(package, &tags, &visited)) {
+        if config.verbosity >= Verbosity::Verbose {
+            eprintln!("could not cache the tags of {}: {err}", package.name);
+        }
+    }
+
+    Ok((tags, visited.into_iter().collect()))
 }
 
-#[derive(PartialEq, Eq, Debug)]
-enum JsonEvent<'src> {
-    EnterObj,         // {
-    ExitObj,          // }
-    EnterList,        // [
-    ExitList,         // ]
-    Entry(&'src str), // <str>:
-    Str(&'src str),   // <str>[,]
+/// Add an entry named by the qualified path of every tag.
+fn add_qualified(tags: &mut Vec<Tag>) {
+    let qualified: Vec<_> = tags
+        .iter()
+        .map(|tag| Tag {
+            name: tag.qualified_name(),
+            scope: String::new(),
+            ..tag.clone()
+        })
+        .collect();
+    tags.extend(qualified);
 }
 
-impl<'src> JsonScanner<'src> {
-    fn new(json: &'src str) -> Self {
-        Self {
-            i: 0,
-            cs: json.chars().peekable(),
-            json: json,
+/// Write the tags of `dependencies` to `output`, a file or the directory of
+/// split tags files, returning the source files of the path packages among
+/// them.
+fn create_tags(
+    dependencies: &[Package],
+    output: &Path,
+    cache: Option<&Cache>,
+    config: &Config,
+) -> Result<Vec<PathBuf>, AnyError> {
+    let jobs = config.jobs.unwrap_or_else(jobs::default_jobs);
+    let jobserver = Jobserver::from_env();
+    if jobserver.is_some() && config.verbosity >= Verbosity::Verbose {
+        eprintln!("using the jobserver of the parent process");
+    }
+
+    // errors are not `Send`, carry their message out of the workers
+    let results = jobs::run(dependencies, jobs, jobserver.as_ref(), |package| {
+        tag_package(package, cache, config).map_err(|err| err.to_string())
+    });
+
+    // results come in package order, so the output does not depend on timing
+    let dir = match config.split {
+        true => output,
+        false => output.parent().unwrap_or(output),
+    };
+    let paths = PathMap::new(config, dir);
+    let mut ranked = vec![];
+    let mut files = vec![];
+    for (package, result) in dependencies.iter().zip(results) {
+        let (mut package_tags, package_files) = result?;
+        for tag in &mut package_tags {
+            tag.file = paths.map(&tag.file);
+        }
+        ranked.push((config.rank(package.tier), package, package_tags));
+        if !package.is_immutable() {
+            files.extend(package_files);
         }
     }
-}
 
-impl<'src> Iterator for JsonScanner<'src> {
-    type Item = JsonEvent<'src>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        use JsonEvent::*;
-
-        while let Some(c) = self.cs.next() {
-            self.i += c.len_utf8();
-            if c == '{' {
-                return Some(EnterObj);
-            } else if c == '}' {
-                return Some(ExitObj);
-            } else if c == '[' {
-                return Some(EnterList);
-            } else if c == ']' {
-                return Some(ExitList);
-            } else if c == '"' {
-                let start = self.i; // first char after quotes
-                while let Some(c) = self.cs.next() {
-                    match c {
-                        '\\' => {
-                            self.i += c.len_utf8();
-                            let c = self.cs.next().unwrap();
-                            self.i += c.len_utf8();
-                        }
-                        '"' => {
-                            let jstr = &self.json[start..self.i];
-                            self.i += c.len_utf8();
-                            match self.cs.peek() {
-                                Some(':') => return Some(Entry(jstr)),
-                                _ => return Some(Str(jstr)),
-                            }
-                        }
-                        _ => self.i += c.len_utf8(),
-                    }
-                }
-
-                return None; // unrechable (assuming given json is correct)
+    // stable, so packages of the same tier keep their order
+    ranked.sort_by_key(|(rank, ..)| *rank);
+
+    // the other formats have the qualified path apart
+    let qualified = config.qualified && config.format.is_tags_file();
+    let packages: Vec<_> = ranked
+        .into_iter()
+        .map(|(_, package, mut tags)| {
+            if qualified {
+                add_qualified(&mut tags);
             }
+            (package, tags)
+        })
+        .collect();
+
+    if config.split {
+        split::write(packages, output, config)?;
+        return Ok(files);
+    }
+
+    // the target directory does not exist before the first build
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    if config.format == Format::Sqlite {
+        sqlite::write(&packages, output, config)?;
+        return Ok(files);
+    }
+
+    let existing = match config.append {
+        true => match fs::read_to_string(output) {
+            Ok(existing) => Some(existing),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        },
+        false => None,
+    };
+
+    write_atomic(output, |w| {
+        tags::write(config.format, packages, existing.as_deref(), w)
+    })?;
+    Ok(files)
+}
+
+/// Write the tags of `dependencies` to `output`, with the fingerprint of the
+/// `inputs` they were generated from.
+fn generate(
+    dependencies: &[Package],
+    output: &Path,
+    inputs: u64,
+    workspace_root: &Path,
+    config: &Config,
+) -> Result<(), AnyError> {
+    let cache = match config.cache {
+        true => Cache::open(workspace_root, config),
+        false => None,
+    };
+    let files = create_tags(dependencies, output, cache.as_ref(), config)?;
+    fingerprint::write(output, inputs, &files)?;
+    Ok(())
+}
+
+/// Print the packages that would be tagged and their source roots.
+fn list_packages(dependencies: &[Package], config: &Config, w: &mut impl Write) -> io::Result<()> {
+    for package in dependencies {
+        let roots = package
+            .targets
+            .iter()
+            .filter(|target| config.tags_target(&target.kind));
+
+        if config.message_format == MessageFormat::Json {
+            let string = |s: &str| Json::Str(s.to_owned());
+            let roots = roots
+                .map(|target| {
+                    Json::Obj(vec![
+                        ("name".to_owned(), string(&target.name)),
+                        (
+                            "kind".to_owned(),
+                            Json::List(target.kind.iter().map(|kind| string(kind)).collect()),
+                        ),
+                        (
+                            "src_path".to_owned(),
+                            string(&target.src_path.to_string_lossy()),
+                        ),
+                    ])
+                })
+                .collect();
+            let record = Json::Obj(vec![
+                ("name".to_owned(), string(&package.name)),
+                ("version".to_owned(), string(&package.version)),
+                ("id".to_owned(), string(&package.id)),
+                (
+                    "source".to_owned(),
+                    package.source.as_deref().map_or(Json::Null, string),
+                ),
+                (
+                    "source_kind".to_owned(),
+                    string(package.source_kind().name()),
+                ),
+                ("tier".to_owned(), string(package.tier.name())),
+                ("roots".to_owned(), Json::List(roots)),
+            ]);
+            writeln!(w, "{record}")?;
+            continue;
         }
 
-        None
+        writeln!(
+            w,
+            "{} {} ({}, {})",
+            package.name,
+            package.version,
+            package.source_kind().name(),
+            package.tier.name()
+        )?;
+        for target in roots {
+            writeln!(
+                w,
+                "    {}: {}",
+                target.kind.join(", "),
+                target.src_path.display()
+            )?;
+        }
     }
+
+    Ok(())
 }
 
-fn use_cargo_metadata() -> Result<String, AnyError> {
-    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
+/// Run `command`, feeding it `stdin` from another thread so neither side
+/// blocks on a full pipe.
+fn run(mut command: Command, stdin: Vec<u8>, config: &Config) -> Result<Vec<u8>, AnyError> {
+    if config.verbosity >= Verbosity::Verbose {
+        eprintln!("running {command:?}");
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("could not run {:?}: {err}", command.get_program()))?;
 
-    let output = Command::new(cargo)
-        .args(["metadata", "--format-version", "1"])
-        .output()?;
+    let mut pipe = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || pipe.write_all(&stdin));
+    let output = child.wait_with_output()?;
+    writer.join().expect("stdin writer panicked")?;
 
     if !output.status.success() {
         return Err(String::from_utf8(output.stderr)?.into());
     }
 
-    let metadata = String::from_utf8(output.stdout)?;
-    Ok(metadata)
+    Ok(output.stdout)
 }
 
-fn get_dependencies(metadata: &str) -> Vec<&str> {
-    use JsonEvent::*;
-    let scanner = JsonScanner::new(metadata);
-
-    let mut dependencies = vec![];
-
-    let mut tp_lvl = false; // top level
-    let mut pkg = false; // package
-    let mut pkg_lst = false; // package list
-    let mut pkg_lst_itm = false; // package list item
-    let mut mnfst = false; // manifest
-    let mut dp = 0; // depth
-
-    // {                            (dp == 1 && EnterObj)
-    //     "package":               (dp == 1 && tp_lvl && Entry("package"))
-    //     [                        (dp == 2 && tp_lvl && pkg && EnterList)
-    //         {                    (dp == 3 && tp_lvl && pkg && pkg_lst && EnterObj)
-    //             ...,
-    //             "manifest_path": (dp == 3 && tp_lvl && pkg && pkg_lst && pkg_lst_itm && Entry("manifest_path"))
-    //             Str(...),        (dp == 3 && tp_lvl && pkg && pkg_lst && pkg_lst_itm && mnfst && Str(<path>))
-    //             ...
-    //         },                   (dp == 2 && tp_lvl && pkg && pkg_lst && pkg_lst_itm, ExitObj)
-    //         ...
-    //     ],                       (dp == 1 && tp_lvl && pkg && pkg_lst && ExitList) -> break
-    //     ...
-    // }
-    for event in scanner {
-        if event == EnterObj || event == EnterList {
-            dp += 1;
-        } else if event == ExitObj || event == ExitList {
-            dp -= 1;
-        }
+/// Create a file at a temporary sibling of `path` with `create`, then rename
+/// it over `path`, removing the temporary file when either fails.
+fn replace_file<E: From<io::Error>>(
+    path: &Path,
+    create: impl FnOnce(&Path) -> Result<(), E>,
+) -> Result<(), E> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.{}.tmp", process::id()));
 
-        match (dp, tp_lvl, pkg, pkg_lst, pkg_lst_itm, mnfst, event) {
-            (1, false, .., EnterObj) => tp_lvl = true,
-            (1, true, false, .., Entry("packages")) => pkg = true,
-            (2, true, true, false, .., EnterList) => pkg_lst = true,
-            (3, true, true, true, false, .., EnterObj) => pkg_lst_itm = true,
-            (3, true, true, true, true, .., Entry("manifest_path")) => mnfst = true,
-            (3, true, true, true, true, true, event) => {
-                if let Str(path) = event {
-                    dependencies.push(path);
-                }
-                mnfst = false;
-            }
-            (2, true, true, true, .., ExitObj) => pkg_lst_itm = false,
-            (1, true, true, .., ExitList) => break,
-            _ => (),
+    match create(&temp).and_then(|()| Ok(fs::rename(&temp, path)?)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(&temp);
+            Err(err)
         }
     }
+}
 
-    return dependencies;
+/// Write a file through a temporary sibling renamed over `path`, so readers
+/// never observe a partially written file.
+fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    replace_file(path, |temp| {
+        let mut w = BufWriter::new(File::create(temp)?);
+        write(&mut w)?;
+        w.into_inner()?.sync_all()
+    })
 }
 
-fn create_tags(dependencies: &[&str]) -> Result<(), AnyError> {
-    let right_length = "Cargo.toml".len();
-    for path in dependencies.iter() {
-        let length = path.len();
-        let dep_path = &path[..length - right_length];
-        let source_path = [dep_path, "src/"].concat();
-        eprintln!("running ctags on {source_path}");
-        let output = Command::new("ctags")
-            .args(["--recurse", "--append", "--languages=Rust", &source_path])
-            .output()?;
-
-        if !output.status.success() {
-            return Err(String::from_utf8(output.stderr)?.into());
+fn real_main() -> Result<i32, AnyError> {
+    let args: Vec<String> = env::args().collect();
+    let config = match cli::parse(args.clone())? {
+        Action::Run(config) => *config,
+        Action::Help => {
+            cli::print_help();
+            return Ok(0);
+        }
+        Action::Version => {
+            cli::print_version();
+            return Ok(0);
         }
+    };
+
+    let metadata = use_cargo_metadata(&config)?;
+    let metadata =
+        json::parse(&metadata).map_err(|err| format!("invalid cargo metadata: {err}"))?;
+
+    // the options needed to run cargo metadata cannot come from its output,
+    // the others are parsed again on top of the settings found in it
+    let workspace_root = Path::new(workspace_root(&metadata));
+    let Action::Run(config) = cli::parse_with_metadata(args, &metadata, workspace_root)? else {
+        unreachable!("help and version were handled above");
+    };
+
+    let mut dependencies = get_dependencies(&metadata, &config);
+    if config.std {
+        dependencies.extend(std_packages(&metadata, &config)?);
     }
+    dependencies.retain(|package| filter::selects(&config.include, &config.exclude, package));
 
-    Ok(())
-}
+    if config.list {
+        list_packages(&dependencies, &config, &mut io::stdout().lock())?;
+        return Ok(0);
+    }
 
-fn real_main() -> Result<i32, AnyError> {
-    let metadata = use_cargo_metadata()?;
-    let dependencies = get_dependencies(&metadata);
-    create_tags(&dependencies)?;
+    let output = config.output(workspace_root, Path::new(target_directory(&metadata)));
+    let output = env::current_dir()?.join(output);
+    let inputs = fingerprint::inputs(workspace_root, &dependencies, &config);
+
+    // `find` and `lsp` read the tags files as they are, only generating
+    // missing ones
+    if config.subcommand != Subcommand::Tag {
+        let tags_files = || match config.split {
+            true => split::indexed_files(&output, config.format),
+            false => output.exists().then(|| vec![output.clone()]),
+        };
+        let files = match tags_files() {
+            Some(files) => files,
+            None => {
+                generate(&dependencies, &output, inputs, workspace_root, &config)?;
+                tags_files().unwrap_or_default()
+            }
+        };
+        return match &config.subcommand {
+            Subcommand::Find(pattern) => {
+                let found = find::find(pattern, config.match_mode, &files)?;
+                Ok(if found { 0 } else { 1 })
+            }
+            Subcommand::Lsp => lsp::serve(files, &config),
+            Subcommand::Tag => unreachable!("tagging was handled above"),
+        };
+    }
+
+    let fresh = fingerprint::is_fresh(&output, inputs);
+    if config.check {
+        if !fresh && config.verbosity >= Verbosity::Normal {
+            eprintln!("{} is out of date", output.display());
+        }
+        return Ok(if fresh { 0 } else { 1 });
+    }
+    if fresh && !config.force {
+        if config.verbosity >= Verbosity::Normal {
+            eprintln!("{} is up to date", output.display());
+        }
+        return Ok(0);
+    }
+
+    generate(&dependencies, &output, inputs, workspace_root, &config)?;
     Ok(0)
 }
 
@@ -182,3 +417,86 @@ fn main() {
 
     exit(code);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Target, Tier};
+
+    #[test]
+    fn listed_records() {
+        let target = |name: &str, kind: &str| Target {
+            name: name.to_owned(),
+            kind: vec![kind.to_owned()],
+            src_path: PathBuf::from(format!("/src/{name}.rs")),
+        };
+        let packages = [
+            Package {
+                id: "app 0.1.0 (path+file:///src)".to_owned(),
+                name: "app".to_owned(),
+                version: "0.1.0".to_owned(),
+                tier: Tier::Workspace,
+                targets: vec![target("app", "lib"), target("bench", "bench")],
+                ..Package::default()
+            },
+            Package {
+                id: "serde 1.0.0".to_owned(),
+                source: Some("registry+https://x".to_owned()),
+                name: "serde".to_owned(),
+                version: "1.0.0".to_owned(),
+                tier: Tier::Direct,
+                ..Package::default()
+            },
+        ];
+        let config = Config {
+            message_format: MessageFormat::Json,
+            ..Config::default()
+        };
+        let mut w = vec![];
+        list_packages(&packages, &config, &mut w).unwrap();
+
+        let records: Vec<_> = String::from_utf8(w)
+            .unwrap()
+            .lines()
+            .map(|line| json::parse(line).unwrap())
+            .collect();
+        assert_eq!(
+            records[0].to_string(),
+            r#"{"name":"app","version":"0.1.0","id":"app 0.1.0 (path+file:///src)","source":null,"#
+                .to_owned()
+                + r#""source_kind":"path","tier":"workspace","#
+                + r#""roots":[{"name":"app","kind":["lib"],"src_path":"/src/app.rs"}]}"#
+        );
+        assert_eq!(
+            records[1].get("source").and_then(Json::as_str),
+            Some("registry+https://x")
+        );
+        assert_eq!(
+            records[1].get("source_kind").and_then(Json::as_str),
+            Some("registry")
+        );
+        assert_eq!(records[1].get("roots"), Some(&Json::List(vec![])));
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn listed_text() {
+        let package = Package {
+            name: "app".to_owned(),
+            version: "0.1.0".to_owned(),
+            tier: Tier::Workspace,
+            targets: vec![Target {
+                name: "app".to_owned(),
+                kind: vec!["lib".to_owned(), "proc-macro".to_owned()],
+                src_path: PathBuf::from("/src/lib.rs"),
+            }],
+            ..Package::default()
+        };
+        let mut w = vec![];
+        list_packages(&[package], &Config::default(), &mut w).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "app 0.1.0 (path, workspace)\n    lib, proc-macro: /src/lib.rs\n"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cli::{Config, Verbosity},
+    json::Json,
+    AnyError,
+};
+
+pub fn use_cargo_metadata(config: &Config) -> Result<String, AnyError> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
+
+    let mut command = Command::new(cargo);
+    command.args(["metadata", "--format-version", "1"]);
+    if let Some(manifest_path) = &config.manifest_path {
+        command.arg("--manifest-path").arg(manifest_path);
+    }
+    if !config.features.is_empty() {
+        command.args(["--features", &config.features.join(",")]);
+    }
+    if config.all_features {
+        command.arg("--all-features");
+    }
+    if config.no_default_features {
+        command.arg("--no-default-features");
+    }
+    if let Some(platform) = &config.filter_platform {
+        command.args(["--filter-platform", platform]);
+    }
+
+    if config.verbosity >= Verbosity::Verbose {
+        eprintln!("running {command:?}");
+    }
+    let output = command.output()?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8(output.stderr)?.into());
+    }
+
+    let metadata = String::from_utf8(output.stdout)?;
+    Ok(metadata)
+}
+
+/// How close a package is to the workspace, which decides the priority of
+/// its tags.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub enum Tier {
+    Workspace,
+    Direct,
+    #[default]
+    Transitive,
+    Std,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 4] = [Tier::Workspace, Tier::Direct, Tier::Transitive, Tier::Std];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "workspace" => Some(Tier::Workspace),
+            "direct" => Some(Tier::Direct),
+            "transitive" => Some(Tier::Transitive),
+            "std" => Some(Tier::Std),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Workspace => "workspace",
+            Tier::Direct => "direct",
+            Tier::Transitive => "transitive",
+            Tier::Std => "std",
+        }
+    }
+}
+
+/// A package listed by `cargo metadata`.
+#[derive(Default, Debug)]
+pub struct Package {
+    pub id: String,
+    /// Where the package comes from, `None` for path packages.
+    pub source: Option<String>,
+    pub name: String,
+    pub version: String,
+    pub tier: Tier,
+    pub targets: Vec<Target>,
+}
+
+/// Where the sources of a package come from.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SourceKind {
+    Registry,
+    Git,
+    /// Local sources, including the standard library.
+    Path,
+}
+
+impl SourceKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "registry" => Some(SourceKind::Registry),
+            "git" => Some(SourceKind::Git),
+            "path" => Some(SourceKind::Path),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceKind::Registry => "registry",
+            SourceKind::Git => "git",
+            SourceKind::Path => "path",
+        }
+    }
+}
+
+impl Package {
+    pub fn source_kind(&self) -> SourceKind {
+        match self.source.as_deref() {
+            Some(source) if source.starts_with("git+") => SourceKind::Git,
+            Some(_) => SourceKind::Registry,
+            None => SourceKind::Path,
+        }
+    }
+
+    /// Whether the sources of the package never change under the same id:
+    /// registry packages are published once, and git ids carry the commit.
+    pub fn is_immutable(&self) -> bool {
+        self.source_kind() != SourceKind::Path
+    }
+}
+
+/// A compilation target of a package: library, binary, build script, ...
+#[derive(Default, Debug)]
+pub struct Target {
+    pub name: String,
+    pub kind: Vec<String>,
+    pub src_path: PathBuf,
+}
+
+impl Target {
+    /// Name of the crate the target compiles to, as used in paths.
+    pub fn crate_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
+}
+
+fn string<'m>(value: &'m Json, key: &str) -> &'m str {
+    value.get(key).and_then(Json::as_str).unwrap_or_default()
+}
+
+/// Whether a `resolve.nodes[].deps[]` edge is of a followed dependency kind.
+fn follows(dep: &Json, config: &Config) -> bool {
+    // cargo before 1.41 does not report kinds
+    let Some(kinds) = dep.get("dep_kinds") else {
+        return true;
+    };
+
+    kinds
+        .items()
+        .iter()
+        .any(|kind| match kind.get("kind").and_then(Json::as_str) {
+            Some("dev") => config.dev,
+            Some("build") => config.build,
+            _ => true,
+        })
+}
+
+/// Depth of every package reachable from the workspace members through the
+/// resolved dependency graph, or `None` when the metadata has no resolve.
+fn resolve_depths<'m>(metadata: &'m Json, config: &Config) -> Option<HashMap<&'m str, usize>> {
+    let resolve = metadata
+        .get("resolve")
+        .filter(|resolve| **resolve != Json::Null)?;
+    let nodes: HashMap<&str, &Json> = resolve
+        .get("nodes")
+        .map_or(&[][..], Json::items)
+        .iter()
+        .map(|node| (string(node, "id"), node))
+        .collect();
+
+    let mut depths = HashMap::new();
+    let mut queue = VecDeque::new();
+    let members = metadata
+        .get("workspace_members")
+        .map_or(&[][..], Json::items);
+    for member in members.iter().filter_map(Json::as_str) {
+        depths.insert(member, 0);
+        queue.push_back(member);
+    }
+
+    // breadth first, so every package gets its shortest distance
+    while let Some(id) = queue.pop_front() {
+        let depth = depths[id];
+        if config.depth.is_some_and(|max| depth >= max) {
+            continue;
+        }
+
+        let deps = nodes.get(id).and_then(|node| node.get("deps"));
+        for dep in deps.map_or(&[][..], Json::items) {
+            let pkg = string(dep, "pkg");
+            if follows(dep, config) && !depths.contains_key(pkg) {
+                depths.insert(pkg, depth + 1);
+                queue.push_back(pkg);
+            }
+        }
+    }
+
+    Some(depths)
+}
+
+/// Packages of the resolved dependency graph of the workspace, within the
+/// configured depth and dependency kinds.
+pub fn get_dependencies(metadata: &Json, config: &Config) -> Vec<Package> {
+    let packages = metadata.get("packages").map_or(&[][..], Json::items);
+    let depths = resolve_depths(metadata, config);
+    let members = metadata
+        .get("workspace_members")
+        .map_or(&[][..], Json::items);
+
+    packages
+        .iter()
+        .filter_map(|package| {
+            let id = string(package, "id");
+            let tier = match depths.as_ref().map(|depths| depths.get(id)) {
+                Some(None) => return None,
+                Some(Some(0)) => Tier::Workspace,
+                Some(Some(1)) => Tier::Direct,
+                Some(Some(_)) => Tier::Transitive,
+                // without a resolve, only membership is known
+                None if members.iter().any(|member| member.as_str() == Some(id)) => Tier::Workspace,
+                None => Tier::Transitive,
+            };
+
+            Some(Package {
+                id: string(package, "id").to_owned(),
+                source: package
+                    .get("source")
+                    .and_then(Json::as_str)
+                    .map(str::to_owned),
+                name: string(package, "name").to_owned(),
+                version: string(package, "version").to_owned(),
+                tier,
+                targets: package
+                    .get("targets")
+                    .map_or(&[][..], Json::items)
+                    .iter()
+                    .map(|target| Target {
+                        name: string(target, "name").to_owned(),
+                        kind: target
+                            .get("kind")
+                            .map_or(&[][..], Json::items)
+                            .iter()
+                            .filter_map(Json::as_str)
+                            .map(str::to_owned)
+                            .collect(),
+                        src_path: string(target, "src_path").into(),
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+pub fn workspace_root(metadata: &Json) -> &str {
+    string(metadata, "workspace_root")
+}
+
+pub fn target_directory(metadata: &Json) -> &str {
+    string(metadata, "target_directory")
+}
+
+/// Run `rustc` with `args` in `dir`, so toolchain overrides of the project
+/// apply, and return its trimmed output.
+fn rustc(args: &[&str], dir: &Path, config: &Config) -> Result<String, AnyError> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+
+    let mut command = Command::new(rustc);
+    command.args(args).current_dir(dir);
+
+    if config.verbosity >= Verbosity::Verbose {
+        eprintln!("running {command:?}");
+    }
+    let output = command.output()?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8(output.stderr)?.into());
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+}
+
+/// The standard library crates, from the rust-src component of the toolchain
+/// used by the workspace.
+pub fn std_packages(metadata: &Json, config: &Config) -> Result<Vec<Package>, AnyError> {
+    let workspace_root = Path::new(workspace_root(metadata));
+    let sysroot = rustc(&["--print", "sysroot"], workspace_root, config)?;
+    let library = Path::new(&sysroot).join("lib/rustlib/src/rust/library");
+    if !library.is_dir() {
+        return Err(format!(
+            "the rust-src component is not installed (looked for {}), \
+             install it with `rustup component add rust-src`",
+            library.display()
+        )
+        .into());
+    }
+
+    // `rustc 1.80.0 (051478957 2024-07-21)`
+    let version = rustc(&["--version"], workspace_root, config)?;
+    let version = version.split(' ').nth(1).unwrap_or_default();
+
+    let packages = ["core", "alloc", "std", "proc_macro"]
+        .into_iter()
+        .map(|name| Package {
+            id: format!("{name}@{version} (rust-src)"),
+            source: None,
+            name: name.to_owned(),
+            version: version.to_owned(),
+            tier: Tier::Std,
+            targets: vec![Target {
+                name: name.to_owned(),
+                kind: vec!["lib".to_owned()],
+                src_path: library.join(name).join("src/lib.rs"),
+            }],
+        })
+        .collect();
+
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    const METADATA: &str = r#"{
+        "packages": [
+            {"id": "app 0.1.0 (path+file:///app)", "name": "app", "version": "0.1.0", "source": null,
+             "targets": [{"name": "app-cli", "kind": ["bin"], "src_path": "/app/src/main.rs"}]},
+            {"id": "serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)", "name": "serde",
+             "version": "1.0.0", "source": "registry+https://github.com/rust-lang/crates.io-index", "targets": []},
+            {"id": "derive 1.0.0", "name": "derive", "version": "1.0.0", "source": "git+https://x/derive?rev=1", "targets": []},
+            {"id": "quickcheck 1.0.0", "name": "quickcheck", "version": "1.0.0", "source": "registry+x", "targets": []},
+            {"id": "rand 0.8.0", "name": "rand", "version": "0.8.0", "source": "registry+x", "targets": []},
+            {"id": "cc 1.0.0", "name": "cc", "version": "1.0.0", "source": "registry+x", "targets": []},
+            {"id": "winapi 0.3.0", "name": "winapi", "version": "0.3.0", "source": "registry+x", "targets": []}
+        ],
+        "workspace_members": ["app 0.1.0 (path+file:///app)"],
+        "resolve": {
+            "nodes": [
+                {"id": "app 0.1.0 (path+file:///app)", "deps": [
+                    {"pkg": "serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)",
+                     "dep_kinds": [{"kind": null}]},
+                    {"pkg": "quickcheck 1.0.0", "dep_kinds": [{"kind": "dev"}]},
+                    {"pkg": "cc 1.0.0", "dep_kinds": [{"kind": "build"}]},
+                    {"pkg": "rand 0.8.0", "dep_kinds": [{"kind": "dev"}, {"kind": null}]}
+                ]},
+                {"id": "serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)",
+                 "deps": [{"pkg": "derive 1.0.0"}]},
+                {"id": "quickcheck 1.0.0", "deps": [{"pkg": "rand 0.8.0", "dep_kinds": [{"kind": null}]}]},
+                {"id": "derive 1.0.0", "deps": []}
+            ]
+        }
+    }"#;
+
+    fn tiers(metadata: &Json, config: &Config) -> Vec<(String, Tier)> {
+        get_dependencies(metadata, config)
+            .into_iter()
+            .map(|package| (package.name, package.tier))
+            .collect()
+    }
+
+    fn named(packages: &[(&str, Tier)]) -> Vec<(String, Tier)> {
+        packages
+            .iter()
+            .map(|&(name, tier)| (name.to_owned(), tier))
+            .collect()
+    }
+
+    #[test]
+    fn depths() {
+        let metadata = json::parse(METADATA).unwrap();
+        let config = Config::default();
+        let depths = resolve_depths(&metadata, &config).unwrap();
+        assert_eq!(depths["app 0.1.0 (path+file:///app)"], 0);
+        assert_eq!(depths["derive 1.0.0"], 2);
+        // the shortest path wins over the one through quickcheck
+        assert_eq!(depths["rand 0.8.0"], 1);
+        assert!(!depths.contains_key("winapi 0.3.0"));
+
+        use Tier::*;
+        assert_eq!(
+            tiers(&metadata, &config),
+            named(&[
+                ("app", Workspace),
+                ("serde", Direct),
+                ("derive", Transitive),
+                ("quickcheck", Direct),
+                ("rand", Direct),
+                ("cc", Direct),
+            ])
+        );
+
+        let config = Config {
+            depth: Some(1),
+            ..Config::default()
+        };
+        let names: Vec<_> = tiers(&metadata, &config)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["app", "serde", "quickcheck", "rand", "cc"]);
+
+        let config = Config {
+            depth: Some(0),
+            ..Config::default()
+        };
+        assert_eq!(tiers(&metadata, &config), named(&[("app", Workspace)]));
+    }
+
+    #[test]
+    fn dependency_kinds() {
+        let metadata = json::parse(METADATA).unwrap();
+        use Tier::*;
+
+        // rand is also a normal dependency, and kept
+        let config = Config {
+            dev: false,
+            ..Config::default()
+        };
+        assert_eq!(
+            tiers(&metadata, &config),
+            named(&[
+                ("app", Workspace),
+                ("serde", Direct),
+                ("derive", Transitive),
+                ("rand", Direct),
+                ("cc", Direct),
+            ])
+        );
+
+        let config = Config {
+            dev: false,
+            build: false,
+            ..Config::default()
+        };
+        let names: Vec<_> = tiers(&metadata, &config)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["app", "serde", "derive", "rand"]);
+    }
+
+    #[test]
+    fn packages() {
+        let metadata = json::parse(METADATA).unwrap();
+        let packages = get_dependencies(&metadata, &Config::default());
+
+        let app = &packages[0];
+        assert_eq!(app.id, "app 0.1.0 (path+file:///app)");
+        assert_eq!((app.name.as_str(), app.version.as_str()), ("app", "0.1.0"));
+        assert_eq!(app.source_kind(), SourceKind::Path);
+        assert!(!app.is_immutable());
+        let [target] = &app.targets[..] else {
+            panic!("{:?}", app.targets);
+        };
+        assert_eq!(target.crate_name(), "app_cli");
+        assert_eq!(target.kind, ["bin"]);
+        assert_eq!(target.src_path, Path::new("/app/src/main.rs"));
+
+        assert_eq!(packages[1].source_kind(), SourceKind::Registry);
+        assert_eq!(packages[2].source_kind(), SourceKind::Git);
+        assert!(packages[2].is_immutable());
+    }
+
+    #[test]
+    fn without_resolve() {
+        let mut metadata = json::parse(METADATA).unwrap();
+        let Json::Obj(entries) = &mut metadata else {
+            unreachable!();
+        };
+        for (key, value) in entries.iter_mut() {
+            if key == "resolve" {
+                *value = Json::Null;
+            }
+        }
+        assert!(resolve_depths(&metadata, &Config::default()).is_none());
+
+        // every package is kept, with only the members told apart
+        let config = Config {
+            depth: Some(0),
+            dev: false,
+            ..Config::default()
+        };
+        let tiers = tiers(&metadata, &config);
+        assert_eq!(tiers.len(), 7);
+        assert_eq!(tiers[0], ("app".to_owned(), Tier::Workspace));
+        assert!(tiers[1..].iter().all(|(_, tier)| *tier == Tier::Transitive));
+    }
+}
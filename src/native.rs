@@ -0,0 +1,1282 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    tags::{line_offsets, Kind, Tag, Visibility},
+    AnyError,
+};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum TokenKind {
+    Ident,
+    Lifetime,
+    Literal,
+    Punct,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Token<'src> {
+    kind: TokenKind,
+    text: &'src str,
+    line: usize,
+    /// Column in characters from 1.
+    column: usize,
+}
+
+impl Token<'_> {
+    fn is(&self, text: &str) -> bool {
+        self.kind != TokenKind::Literal && self.text == text
+    }
+
+    fn is_ident(&self) -> bool {
+        self.kind == TokenKind::Ident
+    }
+}
+
+/// Splits Rust source into tokens, dropping whitespace and comments.
+struct Lexer<'src> {
+    src: &'src str,
+    pos: usize,
+    line: usize,
+    /// Byte offset of the start of the current line.
+    line_start: usize,
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b == b'_' || b.is_ascii_alphanumeric() || b >= 0x80
+}
+
+impl<'src> Lexer<'src> {
+    fn new(src: &'src str) -> Self {
+        Self {
+            src,
+            pos: 0,
+            line: 1,
+            line_start: 0,
+        }
+    }
+
+    fn peek(&self, n: usize) -> Option<u8> {
+        self.src.as_bytes().get(self.pos + n).copied()
+    }
+
+    /// Step over the byte at `pos`, if any: literals and comments cut short
+    /// by the end of input stop there.
+    fn bump(&mut self) {
+        match self.peek(0) {
+            None => return,
+            Some(b'\n') => {
+                self.line += 1;
+                self.line_start = self.pos + 1;
+            }
+            Some(_) => {}
+        }
+        self.pos += 1;
+    }
+
+    fn bump_while(&mut self, f: impl Fn(u8) -> bool) {
+        while self.peek(0).is_some_and(&f) {
+            self.bump();
+        }
+    }
+
+    fn skip_block_comment(&mut self) {
+        let mut depth = 0;
+        while let Some(b) = self.peek(0) {
+            if b == b'/' && self.peek(1) == Some(b'*') {
+                depth += 1;
+                self.bump();
+            } else if b == b'*' && self.peek(1) == Some(b'/') {
+                depth -= 1;
+                self.bump();
+                if depth == 0 {
+                    self.bump();
+                    return;
+                }
+            }
+            self.bump();
+        }
+    }
+
+    /// Skip a quoted literal, the opening quote being at `pos`.
+    fn skip_quoted(&mut self, quote: u8) {
+        self.bump();
+        while let Some(b) = self.peek(0) {
+            self.bump();
+            if b == b'\\' {
+                // the escaped byte, unless the input ends there
+                self.bump();
+            } else if b == quote {
+                return;
+            }
+        }
+    }
+
+    /// Skip a raw string, `pos` being at the `#`s or `"` following the `r`.
+    fn skip_raw_string(&mut self) {
+        let start = self.pos;
+        self.bump_while(|b| b == b'#');
+        let hashes = self.pos - start;
+        self.bump(); // opening quote
+        while let Some(b) = self.peek(0) {
+            self.bump();
+            if b == b'"' && (0..hashes).all(|n| self.peek(n) == Some(b'#')) {
+                self.pos += hashes;
+                return;
+            }
+        }
+    }
+
+    /// Either a char literal or a lifetime, `pos` being at the quote.
+    fn quote(&mut self) -> TokenKind {
+        let rest = &self.src[self.pos + 1..];
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some('\\'), _) => {
+                self.skip_quoted(b'\'');
+                TokenKind::Literal
+            }
+            (Some(c), Some('\'')) => {
+                self.pos += 2 + c.len_utf8();
+                TokenKind::Literal
+            }
+            _ => {
+                self.bump();
+                self.bump_while(is_ident_byte);
+                TokenKind::Lifetime
+            }
+        }
+    }
+}
+
+impl<'src> Iterator for Lexer<'src> {
+    type Item = Token<'src>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let b = self.peek(0)?;
+            if b.is_ascii_whitespace() {
+                self.bump();
+            } else if b == b'/' && self.peek(1) == Some(b'/') {
+                self.bump_while(|b| b != b'\n');
+            } else if b == b'/' && self.peek(1) == Some(b'*') {
+                self.skip_block_comment();
+            } else {
+                break;
+            }
+        }
+
+        let start = self.pos;
+        let line = self.line;
+        let column = self.src[self.line_start..start].chars().count() + 1;
+        let b = self.peek(0)?;
+        let next = self.peek(1);
+
+        let kind = match b {
+            b'"' => {
+                self.skip_quoted(b'"');
+                TokenKind::Literal
+            }
+            b'\'' => self.quote(),
+            // raw strings: r"", r#""#, br"", cr""
+            b'r' if matches!(next, Some(b'"') | Some(b'#'))
+                && !(next == Some(b'#') && self.peek(2).is_some_and(is_ident_byte)) =>
+            {
+                self.bump();
+                self.skip_raw_string();
+                TokenKind::Literal
+            }
+            b'b' | b'c'
+                if next == Some(b'r') && matches!(self.peek(2), Some(b'"') | Some(b'#')) =>
+            {
+                self.pos += 2;
+                self.skip_raw_string();
+                TokenKind::Literal
+            }
+            // byte and C strings: b"", b'', c""
+            b'b' | b'c' if matches!(next, Some(b'"') | Some(b'\'')) => {
+                self.bump();
+                self.skip_quoted(next.unwrap());
+                TokenKind::Literal
+            }
+            // raw identifiers: r#ident
+            b'r' if next == Some(b'#') => {
+                self.pos += 2;
+                self.bump_while(is_ident_byte);
+                let text = &self.src[start + 2..self.pos];
+                return Some(Token {
+                    kind: TokenKind::Ident,
+                    text,
+                    line,
+                    column,
+                });
+            }
+            b'0'..=b'9' => {
+                while let Some(b) = self.peek(0) {
+                    let fraction = b == b'.' && self.peek(1).is_some_and(|b| b.is_ascii_digit());
+                    if !is_ident_byte(b) && !fraction {
+                        break;
+                    }
+                    self.bump();
+                }
+                TokenKind::Literal
+            }
+            _ if is_ident_byte(b) => {
+                self.bump_while(is_ident_byte);
+                TokenKind::Ident
+            }
+            _ => {
+                let pair = [b, next.unwrap_or(0)];
+                if matches!(&pair, b"::" | b"->" | b"=>") {
+                    self.pos += 2;
+                } else {
+                    // step over a whole char so `pos` stays on a boundary
+                    let c = self.src[start..].chars().next()?;
+                    self.pos += c.len_utf8();
+                }
+                TokenKind::Punct
+            }
+        };
+
+        Some(Token {
+            kind,
+            text: &self.src[start..self.pos],
+            line,
+            column,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum ScopeKind {
+    Block,
+    /// The body of a macro invocation such as `cfg_if! { ... }`, or a block
+    /// within it, whose items are those of the enclosing module.
+    Macro,
+    Fn,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Module,
+}
+
+struct Scope {
+    kind: ScopeKind,
+    /// Parenthesis and bracket nesting at the opening brace.
+    nest: usize,
+    /// Name of a module, impl self type, trait or type, as it appears in the
+    /// paths of the items within.
+    name: Option<String>,
+    /// Name and `#[path]` of an inline module.
+    module: Option<(String, Option<String>)>,
+    /// Visibility of the items within that is not their own: that of the
+    /// trait or enum, or public in trait impls.
+    members: Option<Visibility>,
+    /// Index of the item the scope is the body of, which ends with it.
+    item: Option<usize>,
+}
+
+/// An item found in a Rust source file.
+#[derive(Debug)]
+pub struct Item {
+    pub name: String,
+    pub kind: Kind,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub visibility: Visibility,
+    /// Path of the item within the file: inline modules, impl self type,
+    /// trait or enum.
+    pub scope: Vec<String>,
+}
+
+/// A `mod name;` declaration, whose items live in another file.
+#[derive(Debug)]
+pub struct ModDecl {
+    pub name: String,
+    /// Value of a `#[path = "..."]` attribute.
+    pub path: Option<String>,
+    /// Name and `#[path]` of the inline modules the declaration is nested in.
+    pub parents: Vec<(String, Option<String>)>,
+    pub visibility: Visibility,
+}
+
+/// A `pub use` declaration, making an item public at a path other than its
+/// own.
+#[derive(Debug)]
+pub struct Reexport {
+    /// Path of the module the declaration is in.
+    pub module: String,
+    /// Path of the item, ending with `*` for glob imports.
+    pub path: String,
+}
+
+/// What makes the items of a crate reachable from other crates.
+#[derive(Default, Debug)]
+pub struct Exports {
+    /// Paths of the modules, with their visibility.
+    pub modules: Vec<(String, Visibility)>,
+    pub reexports: Vec<Reexport>,
+}
+
+#[derive(Debug)]
+pub struct Scan {
+    pub items: Vec<Item>,
+    pub mods: Vec<ModDecl>,
+    /// `pub use` declarations, with paths as written and modules relative to
+    /// the file.
+    pub reexports: Vec<Reexport>,
+    /// Declarations in `macro_rules!` bodies, assumed to be expanded at the
+    /// crate root.
+    pub macro_mods: Vec<ModDecl>,
+}
+
+/// Contents of a string literal token, raw or not.
+fn unquote(literal: &str) -> &str {
+    literal
+        .trim_start_matches('r')
+        .trim_matches('#')
+        .trim_matches('"')
+}
+
+/// Whether an item keyword following `prev` starts an item, rather than
+/// being part of a type or expression (`*const T`, `<const N: usize>`,
+/// `-> impl Trait`).
+fn item_start(prev: Option<&Token>) -> bool {
+    let Some(prev) = prev else {
+        return true;
+    };
+    match prev.kind {
+        TokenKind::Punct => matches!(prev.text, ";" | "{" | "}" | "]" | ")"),
+        TokenKind::Ident => matches!(
+            prev.text,
+            "pub" | "unsafe" | "default" | "extern" | "async" | "const"
+        ),
+        TokenKind::Literal => true, // extern "C"
+        TokenKind::Lifetime => false,
+    }
+}
+
+/// Name of the self type of an impl block, `tokens` being everything between
+/// the `impl` keyword and the opening brace.
+fn impl_name<'src>(tokens: &[Token<'src>]) -> Option<&'src str> {
+    let mut angle = 0usize;
+    let mut skip = 0;
+    let mut self_ty = tokens;
+
+    // skip generic parameters
+    if tokens.first().is_some_and(|t| t.is("<")) {
+        for (i, token) in tokens.iter().enumerate() {
+            match token.text {
+                "<" => angle += 1,
+                ">" => angle -= 1,
+                _ => (),
+            }
+            if angle == 0 {
+                skip = i + 1;
+                break;
+            }
+        }
+    }
+
+    // `impl Trait for Type`, ignoring `for<'a>` bounds
+    for (i, token) in tokens.iter().enumerate().skip(skip) {
+        match token.text {
+            "<" => angle += 1,
+            ">" => angle = angle.saturating_sub(1),
+            "where" if angle == 0 => break,
+            "for" if angle == 0 && !tokens.get(i + 1).is_some_and(|t| t.is("<")) => {
+                self_ty = &tokens[i + 1..];
+                skip = 0;
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    let mut self_ty = &self_ty[skip.min(self_ty.len())..];
+    let mut name = None;
+    let mut angle = 0usize;
+    for (i, token) in self_ty.iter().enumerate() {
+        match token.text {
+            "<" => angle += 1,
+            ">" => angle = angle.saturating_sub(1),
+            "where" | "+" if angle == 0 => {
+                self_ty = &self_ty[..i];
+                break;
+            }
+            "dyn" | "mut" | "const" => (),
+            _ if token.is_ident() && angle == 0 => name = Some(token.text),
+            _ => (),
+        }
+    }
+
+    name.or_else(|| self_ty.iter().find(|t| t.is_ident()).map(|t| t.text))
+}
+
+/// Visibility of the item whose keyword is `tokens[i]`, given by the `pub`
+/// in front of it, or for `macro_rules!` by a `#[macro_export]` attribute.
+fn item_visibility(tokens: &[Token], i: usize) -> Visibility {
+    if tokens[i].is("macro_rules") {
+        let mut end = i;
+        while end > 0 && tokens[end - 1].is("]") {
+            let mut depth = 0usize;
+            let open = (0..end).rev().find(|&j| {
+                if tokens[j].is("]") {
+                    depth += 1;
+                } else if tokens[j].is("[") {
+                    depth -= 1;
+                }
+                depth == 0
+            });
+            let Some(open) = open.filter(|&open| open > 0 && tokens[open - 1].is("#")) else {
+                break;
+            };
+            if tokens[open..end].iter().any(|t| t.is("macro_export")) {
+                return Visibility::Public;
+            }
+            end = open - 1;
+        }
+        return Visibility::Private;
+    }
+
+    // qualifiers come between the visibility and the keyword
+    let qualifier = |t: &Token| {
+        t.kind == TokenKind::Literal
+            || ["unsafe", "async", "const", "extern", "default", "auto"]
+                .iter()
+                .any(|q| t.is(q))
+    };
+    let mut j = i;
+    while j > 0 && qualifier(&tokens[j - 1]) {
+        j -= 1;
+    }
+
+    let before = &tokens[..j];
+    match before.last() {
+        Some(t) if t.is("pub") => return Visibility::Public,
+        Some(t) if t.is(")") => (),
+        _ => return Visibility::Private,
+    }
+    let Some(open) = before.iter().rposition(|t| t.is("(")) else {
+        return Visibility::Private;
+    };
+    if !open.checked_sub(1).is_some_and(|k| before[k].is("pub")) {
+        return Visibility::Private;
+    }
+    match before.get(open + 1).map(|t| t.text) {
+        Some("crate") => Visibility::Crate,
+        Some("super") => Visibility::Super,
+        Some("in") => Visibility::Restricted,
+        _ => Visibility::Private,
+    }
+}
+
+/// Paths named by the use tree `tokens`, each following `prefix`. Renames
+/// are left out, the item is the same.
+fn use_paths(tokens: &[Token], prefix: &str, paths: &mut Vec<String>) {
+    let mut path = prefix.to_owned();
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text {
+            "{" => {
+                // split the group at its commas
+                let mut depth = 0usize;
+                let mut start = i + 1;
+                for (j, token) in tokens.iter().enumerate().skip(i + 1) {
+                    match token.text {
+                        "{" => depth += 1,
+                        "}" if depth > 0 => depth -= 1,
+                        "," | "}" if depth == 0 => {
+                            use_paths(&tokens[start..j], &path, paths);
+                            start = j + 1;
+                        }
+                        _ => (),
+                    }
+                }
+                return;
+            }
+            "as" => break,
+            "::" => (),
+            // `a::{self, B}` names `a` itself
+            "self" if i == 0 && !prefix.is_empty() => (),
+            segment => {
+                if !path.is_empty() {
+                    path += "::";
+                }
+                path += segment;
+            }
+        }
+    }
+    if !tokens.is_empty() {
+        paths.push(path);
+    }
+}
+
+/// Path from the crate root of the `path` of a `use` declaration in
+/// `module`. Paths not starting with `crate`, `self` or `super` are taken as
+/// relative to the module, those naming other crates then match no item.
+fn resolve_use(crate_name: &str, module: &str, path: &str) -> String {
+    let mut resolved: Vec<&str> = module.split("::").collect();
+    let mut segments = path.split("::").peekable();
+    match segments.peek() {
+        Some(&"crate") => {
+            resolved = vec![crate_name];
+            segments.next();
+        }
+        Some(&"self") => {
+            segments.next();
+        }
+        _ => (),
+    }
+    while segments.next_if_eq(&"super").is_some() {
+        if resolved.len() > 1 {
+            resolved.pop();
+        }
+    }
+    resolved.extend(segments);
+    resolved.join("::")
+}
+
+/// Whether an impl block implements a trait, `tokens` being everything
+/// between the `impl` keyword and the opening brace.
+fn is_trait_impl(tokens: &[Token]) -> bool {
+    let mut angle = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text {
+            "<" => angle += 1,
+            ">" => angle = angle.saturating_sub(1),
+            "where" if angle == 0 => return false,
+            "for" if angle == 0 && !tokens.get(i + 1).is_some_and(|t| t.is("<")) => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Path within the file of the items of the innermost of `scopes`. Items in
+/// function bodies and blocks cannot be named from outside, they only get
+/// the path of their module.
+fn item_scope(scopes: &[Scope]) -> Vec<String> {
+    let body = scopes
+        .iter()
+        .position(|s| matches!(s.kind, ScopeKind::Fn | ScopeKind::Block));
+    let scopes = match body {
+        Some(body) => &scopes[..body],
+        None => scopes,
+    };
+
+    scopes
+        .iter()
+        .filter(|s| body.is_none() || s.kind == ScopeKind::Module)
+        .filter_map(|s| s.name.clone())
+        .collect()
+}
+
+/// Find the items and module declarations of a Rust source file.
+pub fn scan(source: &str) -> Scan {
+    use ScopeKind as S;
+
+    let tokens: Vec<Token> = Lexer::new(source).collect();
+    let mut items: Vec<Item> = vec![];
+    let mut mods = vec![];
+    let mut macro_mods = vec![];
+    let mut reexports = vec![];
+
+    let mut scopes: Vec<Scope> = vec![];
+    let mut nest = 0usize;
+    // kind of the scope opened by the next brace, and the nesting it is expected at
+    let mut pending: Option<(ScopeKind, usize)> = None;
+    let mut pending_module = None;
+    let mut pending_name = None;
+    let mut pending_members = None;
+    let mut pending_item = None;
+    // items ending at the next `;`, or `,` for variants, found at the given
+    // scope depth and nesting
+    let mut unterminated: Vec<(usize, usize, usize)> = vec![];
+    // `#[path = "..."]` of the next module
+    let mut path_attr: Option<String> = None;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let prev = i.checked_sub(1).map(|i| &tokens[i]);
+        let next = tokens.get(i + 1);
+        let next_ident = next.filter(|t| t.is_ident());
+        // the item named `name`, found at `at`, returning its index
+        let mut item = |name: &str, kind: Kind, at: &Token| {
+            if name == "_" {
+                return None;
+            }
+            let visibility = match kind {
+                // impl blocks have no visibility of their own
+                Kind::Impl => Visibility::Public,
+                _ => scopes
+                    .last()
+                    .and_then(|s| s.members)
+                    .unwrap_or_else(|| item_visibility(&tokens, i)),
+            };
+            items.push(Item {
+                name: name.to_owned(),
+                kind,
+                line: at.line,
+                column: at.column,
+                end_line: at.line,
+                visibility,
+                scope: item_scope(&scopes),
+            });
+            Some(items.len() - 1)
+        };
+
+        if token.kind == TokenKind::Punct {
+            if matches!(token.text, ";" | "{" | "}") {
+                path_attr = None;
+            }
+            match token.text {
+                "#" if tokens.get(i + 1).is_some_and(|t| t.is("["))
+                    && tokens.get(i + 2).is_some_and(|t| t.is("path"))
+                    && tokens.get(i + 3).is_some_and(|t| t.is("=")) =>
+                {
+                    path_attr = tokens
+                        .get(i + 4)
+                        .filter(|t| t.kind == TokenKind::Literal)
+                        .map(|t| unquote(t.text).to_owned());
+                }
+                "(" | "[" => nest += 1,
+                ")" | "]" => nest = nest.saturating_sub(1),
+                "{" => {
+                    let (kind, name, members, item) = match pending {
+                        Some((kind, at)) if at == nest => {
+                            pending = None;
+                            let members = pending_members.take();
+                            (kind, pending_name.take(), members, pending_item.take())
+                        }
+                        _ => {
+                            let invocation = prev.is_some_and(|p| p.is("!"))
+                                && i.checked_sub(2).is_some_and(|j| tokens[j].is_ident());
+                            let in_macro = scopes.last().is_some_and(|s| s.kind == S::Macro);
+                            match invocation || in_macro {
+                                true => (S::Macro, None, None, None),
+                                false => (S::Block, None, None, None),
+                            }
+                        }
+                    };
+                    let module = match kind {
+                        S::Module => pending_module.take(),
+                        _ => None,
+                    };
+                    scopes.push(Scope {
+                        kind,
+                        nest,
+                        name,
+                        module,
+                        members,
+                        item,
+                    });
+                }
+                "}" => {
+                    if let Some(scope) = scopes.pop() {
+                        nest = scope.nest;
+                        if let Some(item) = scope.item {
+                            items[item].end_line = token.line;
+                        }
+                    }
+                    // the last variant of an enum needs no comma
+                    let end = prev.map_or(token.line, |p| p.line);
+                    while let Some(&(item, ..)) = unterminated
+                        .last()
+                        .filter(|(_, depth, _)| *depth > scopes.len())
+                    {
+                        items[item].end_line = end;
+                        unterminated.pop();
+                    }
+                }
+                ";" | "," => {
+                    if token.is(";") && pending.is_some_and(|(_, at)| at == nest) {
+                        pending = None;
+                        if let Some(item) = pending_item.take() {
+                            items[item].end_line = token.line;
+                        }
+                    }
+                    unterminated.retain(|&(item, depth, at)| {
+                        let ends = (depth, at) == (scopes.len(), nest)
+                            && (token.is(";") || items[item].kind == Kind::Variant);
+                        if ends {
+                            items[item].end_line = token.line;
+                        }
+                        !ends
+                    });
+                }
+                _ => (),
+            }
+            i += 1;
+            continue;
+        }
+
+        if !token.is_ident() {
+            i += 1;
+            continue;
+        }
+
+        let scope = scopes.last();
+        let in_enum = scope.is_some_and(|s| s.kind == S::Enum && s.nest == nest);
+        if in_enum && prev.is_some_and(|p| p.is("{") || p.is(",") || p.is("]")) {
+            let depth = scopes.len();
+            unterminated.extend(item(token.text, Kind::Variant, token).map(|v| (v, depth, nest)));
+            i += 1;
+            continue;
+        }
+
+        match (token.text, next_ident) {
+            ("fn", Some(name)) => {
+                let in_impl = scope.is_some_and(|s| matches!(s.kind, S::Impl | S::Trait));
+                let kind = if in_impl {
+                    Kind::Method
+                } else {
+                    Kind::Function
+                };
+                pending_item = item(name.text, kind, name);
+                pending = Some((S::Fn, nest));
+                pending_name = None;
+                pending_members = None;
+            }
+            ("struct", Some(name)) => {
+                pending_item = item(name.text, Kind::Struct, name);
+                pending = Some((S::Struct, nest));
+                pending_name = Some(name.text.to_owned());
+                pending_members = None;
+            }
+            ("union", Some(name))
+                if item_start(prev)
+                    && tokens
+                        .get(i + 2)
+                        .is_some_and(|t| t.is("{") || t.is("<") || t.is("where")) =>
+            {
+                pending_item = item(name.text, Kind::Union, name);
+                pending = Some((S::Struct, nest));
+                pending_name = Some(name.text.to_owned());
+                pending_members = None;
+            }
+            ("enum", Some(name)) => {
+                pending_item = item(name.text, Kind::Enum, name);
+                pending = Some((S::Enum, nest));
+                pending_name = Some(name.text.to_owned());
+                pending_members = pending_item.map(|enum_| items[enum_].visibility);
+            }
+            ("trait", Some(name)) => {
+                pending_item = item(name.text, Kind::Trait, name);
+                pending = Some((S::Trait, nest));
+                pending_name = Some(name.text.to_owned());
+                pending_members = pending_item.map(|trait_| items[trait_].visibility);
+            }
+            ("type", Some(name)) => {
+                let depth = scopes.len();
+                unterminated.extend(item(name.text, Kind::Type, name).map(|t| (t, depth, nest)));
+            }
+            ("const", Some(name))
+                if item_start(prev) && tokens.get(i + 2).is_some_and(|t| t.is(":")) =>
+            {
+                let depth = scopes.len();
+                unterminated.extend(item(name.text, Kind::Const, name).map(|c| (c, depth, nest)));
+            }
+            ("static", Some(_)) if item_start(prev) => {
+                let name = match next_ident {
+                    Some(t) if t.is("mut") => tokens.get(i + 2).filter(|t| t.is_ident()),
+                    name => name,
+                };
+                if let Some(name) = name {
+                    let depth = scopes.len();
+                    let static_ = item(name.text, Kind::Static, name);
+                    unterminated.extend(static_.map(|s| (s, depth, nest)));
+                }
+            }
+            ("mod", Some(name)) => {
+                let module = item(name.text, Kind::Module, name);
+                let path = path_attr.take();
+                if let Some(semi) = tokens.get(i + 2).filter(|t| t.is(";")) {
+                    if let Some(module) = module {
+                        items[module].end_line = semi.line;
+                    }
+                    // declarations in function bodies are not followed
+                    if scopes
+                        .iter()
+                        .all(|s| matches!(s.kind, S::Module | S::Macro))
+                    {
+                        mods.push(ModDecl {
+                            name: name.text.to_owned(),
+                            path,
+                            parents: scopes.iter().filter_map(|s| s.module.clone()).collect(),
+                            visibility: item_visibility(&tokens, i),
+                        });
+                    }
+                } else {
+                    pending_item = module;
+                    pending = Some((S::Module, nest));
+                    pending_name = Some(name.text.to_owned());
+                    pending_members = None;
+                    pending_module = Some((name.text.to_owned(), path));
+                }
+            }
+            ("macro_rules", _) if next.is_some_and(|t| t.is("!")) => {
+                if let Some(name) = tokens.get(i + 2).filter(|t| t.is_ident()) {
+                    let macro_ = item(name.text, Kind::Macro, name);
+                    // the macro body is not Rust items, skip it whole
+                    let mut depth = 0usize;
+                    i += 3;
+                    while let Some(token) = tokens.get(i) {
+                        match token.text {
+                            "(" | "[" | "{" if token.kind == TokenKind::Punct => depth += 1,
+                            ")" | "]" | "}" if token.kind == TokenKind::Punct => {
+                                depth = depth.saturating_sub(1)
+                            }
+                            "mod" if tokens.get(i + 2).is_some_and(|t| t.is(";")) => {
+                                if let Some(name) = tokens.get(i + 1).filter(|t| t.is_ident()) {
+                                    macro_mods.push(ModDecl {
+                                        name: name.text.to_owned(),
+                                        path: None,
+                                        parents: vec![],
+                                        visibility: item_visibility(&tokens, i),
+                                    });
+                                }
+                            }
+                            _ => (),
+                        }
+                        if let Some(macro_) = macro_ {
+                            items[macro_].end_line = token.line;
+                        }
+                        i += 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+            }
+            ("use", _)
+                if item_start(prev)
+                    && scopes
+                        .iter()
+                        .all(|s| matches!(s.kind, S::Module | S::Macro))
+                    && item_visibility(&tokens, i) == Visibility::Public =>
+            {
+                let end = tokens[i..]
+                    .iter()
+                    .position(|t| t.is(";"))
+                    .map_or(tokens.len(), |end| i + end);
+                let module: Vec<_> = scopes.iter().filter_map(|s| s.name.clone()).collect();
+                let mut paths = vec![];
+                use_paths(&tokens[i + 1..end], "", &mut paths);
+                reexports.extend(paths.into_iter().map(|path| Reexport {
+                    module: module.join("::"),
+                    path,
+                }));
+                // the braces of the use tree are no scopes
+                i = end;
+                continue;
+            }
+            ("impl", _) if item_start(prev) => {
+                let header = tokens[i + 1..]
+                    .iter()
+                    .position(|t| t.is("{") || t.is(";"))
+                    .map_or(&tokens[i + 1..], |end| &tokens[i + 1..i + 1 + end]);
+                let name = impl_name(header);
+                pending_item = name.and_then(|name| {
+                    let at = header.iter().find(|t| t.text == name).unwrap_or(token);
+                    item(name, Kind::Impl, at)
+                });
+                pending = Some((S::Impl, nest));
+                pending_name = name.map(str::to_owned);
+                pending_members = is_trait_impl(header).then_some(Visibility::Public);
+            }
+            _ => (),
+        }
+
+        i += 1;
+    }
+
+    Scan {
+        items,
+        mods,
+        reexports,
+        macro_mods,
+    }
+}
+
+/// File a `mod` declaration in `file` refers to, and whether that file owns
+/// its directory like a `mod.rs` does.
+fn resolve_mod(file: &Path, mod_rs: bool, decl: &ModDecl) -> Option<(PathBuf, bool)> {
+    let parent = file.parent()?;
+    if let (true, Some(path)) = (decl.parents.is_empty(), &decl.path) {
+        return Some((parent.join(path), true));
+    }
+
+    let mut dir = match mod_rs {
+        true => parent.to_owned(),
+        false => parent.join(file.file_stem()?),
+    };
+    for (name, path) in &decl.parents {
+        dir.push(path.as_deref().unwrap_or(name));
+    }
+
+    if let Some(path) = &decl.path {
+        return Some((dir.join(path), true));
+    }
+
+    let file = dir.join(format!("{}.rs", decl.name));
+    match file.exists() {
+        true => Some((file, false)),
+        false => Some((dir.join(&decl.name).join("mod.rs"), true)),
+    }
+}
+
+/// Module path of the file a `mod` declaration in the module `parent` refers
+/// to.
+fn mod_path(parent: &str, decl: &ModDecl) -> String {
+    let mut path = parent.to_owned();
+    for (name, _) in &decl.parents {
+        path += "::";
+        path += name;
+    }
+    path + "::" + &decl.name
+}
+
+/// Walk the module tree of the crate `name` rooted at `root`, following `mod`
+/// declarations into their files, and visit each file with its module path.
+/// Files already `visited` are skipped, and missing ones (such as generated
+/// code) are ignored. Returns the modules and `pub use` declarations found,
+/// with paths from the crate root.
+fn walk_module_tree(
+    root: &Path,
+    name: &str,
+    visited: &mut HashSet<PathBuf>,
+    mut visit: impl FnMut(&Path, &str, &str, Vec<Item>),
+) -> Result<Exports, AnyError> {
+    let mut exports = Exports::default();
+    let mut stack = vec![(root.to_owned(), true, name.to_owned())];
+
+    while let Some((file, mod_rs, module)) = stack.pop() {
+        if !visited.insert(file.clone()) {
+            continue;
+        }
+
+        let bytes = match fs::read(&file) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(format!("{}: {err}", file.display()).into()),
+        };
+        let source = String::from_utf8_lossy(&bytes);
+        let scan = scan(&source);
+
+        // reversed, so modules are visited in declaration order
+        for decl in scan.mods.iter().rev() {
+            let path = mod_path(&module, decl);
+            let resolved = resolve_mod(&file, mod_rs, decl);
+            stack.extend(resolved.map(|(file, mod_rs)| (file, mod_rs, path)));
+        }
+        for decl in scan.macro_mods.iter().rev() {
+            let path = mod_path(name, decl);
+            // these are not tagged, so not among the module items below
+            exports.modules.push((path.clone(), decl.visibility));
+            let resolved = resolve_mod(root, true, decl);
+            stack.extend(resolved.map(|(file, mod_rs)| (file, mod_rs, path)));
+        }
+
+        for item in scan.items.iter().filter(|item| item.kind == Kind::Module) {
+            let mut path = module.clone();
+            for name in item.scope.iter().chain([&item.name]) {
+                path += "::";
+                path += name;
+            }
+            exports.modules.push((path, item.visibility));
+        }
+        for reexport in scan.reexports {
+            let module = match reexport.module.is_empty() {
+                true => module.clone(),
+                false => format!("{module}::{}", reexport.module),
+            };
+            let path = resolve_use(name, &module, &reexport.path);
+            exports.reexports.push(Reexport { module, path });
+        }
+        visit(&file, &module, &source, scan.items);
+    }
+
+    Ok(exports)
+}
+
+/// Tag the module tree of the crate `name` rooted at `root`, also returning
+/// what it exports.
+pub fn tag_crate(
+    root: &Path,
+    name: &str,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(Vec<Tag>, Exports), AnyError> {
+    let mut tags = vec![];
+
+    let exports = walk_module_tree(root, name, visited, |file, module, source, items| {
+        let lines = line_offsets(source);
+        tags.extend(items.into_iter().map(|item| {
+            let (offset, pattern) = lines[item.line - 1].clone();
+            let mut scope = module.to_owned();
+            for name in item.scope {
+                scope += "::";
+                scope += &name;
+            }
+            Tag {
+                name: item.name,
+                file: file.to_owned(),
+                line: item.line,
+                column: item.column,
+                end_line: item.end_line,
+                offset,
+                kind: item.kind,
+                pattern,
+                scope,
+                visibility: Some(item.visibility),
+            }
+        }));
+    })?;
+
+    Ok((tags, exports))
+}
+
+/// Source files in the module tree of the crate `name` rooted at `root`, with
+/// their module paths, and what it exports.
+pub fn module_tree(
+    root: &Path,
+    name: &str,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(Vec<(PathBuf, String)>, Exports), AnyError> {
+    let mut files = vec![];
+    let exports = walk_module_tree(root, name, visited, |file, module, _, _| {
+        files.push((file.to_owned(), module.to_owned()))
+    })?;
+    Ok((files, exports))
+}
+
+/// Visibility of the item named `name` declared on the source `line`, or
+/// `None` when the name is not on it, or only within a literal or comment,
+/// as when the line continues a string from the one before. Attributes on the lines before, such
+/// as the `#[macro_export]` of macros, are taken into account when included.
+pub fn declared_visibility(line: &str, name: &str) -> Option<Visibility> {
+    let tokens: Vec<Token> = Lexer::new(line).collect();
+    let at = tokens.iter().position(|t| t.is_ident() && t.text == name)?;
+    // the keyword comes right before the name, but for `static mut` and
+    // `macro_rules!`
+    let keyword = match at.checked_sub(1) {
+        Some(k) if tokens[k].is("mut") || tokens[k].is("!") => k.checked_sub(1),
+        keyword => keyword,
+    };
+    Some(keyword.map_or(Visibility::Private, |k| item_visibility(&tokens, k)))
+}
+
+/// Column of the identifier `name` on the source `line`, in characters
+/// from 1, or `None` when it is not on it outside of literals and comments.
+pub fn declared_column(line: &str, name: &str) -> Option<usize> {
+    Lexer::new(line)
+        .find(|t| t.is_ident() && t.text == name)
+        .map(|t| t.column)
+}
+
+/// Whether the impl block declared on the source `line` implements a trait.
+pub fn declares_trait_impl(line: &str) -> bool {
+    let tokens: Vec<Token> = Lexer::new(line).collect();
+    let Some(start) = tokens.iter().position(|t| t.is("impl")) else {
+        return false;
+    };
+    let header = &tokens[start + 1..];
+    let end = header
+        .iter()
+        .position(|t| t.is("{"))
+        .unwrap_or(header.len());
+    is_trait_impl(&header[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(src: &str) -> Vec<(TokenKind, &str)> {
+        Lexer::new(src).map(|t| (t.kind, t.text)).collect()
+    }
+
+    #[test]
+    fn tokens() {
+        use TokenKind::*;
+        assert_eq!(
+            lex("fn r#type<'a>(x: &'a str) -> char { '\\'' } // end"),
+            [
+                (Ident, "fn"),
+                (Ident, "type"),
+                (Punct, "<"),
+                (Lifetime, "'a"),
+                (Punct, ">"),
+                (Punct, "("),
+                (Ident, "x"),
+                (Punct, ":"),
+                (Punct, "&"),
+                (Lifetime, "'a"),
+                (Ident, "str"),
+                (Punct, ")"),
+                (Punct, "->"),
+                (Ident, "char"),
+                (Punct, "{"),
+                (Literal, "'\\''"),
+                (Punct, "}"),
+            ]
+        );
+        assert_eq!(
+            lex(r###"r#"a "quoted" b"# b"\"" /* a /* nested */ comment */ 1.5e3"###),
+            [
+                (Literal, r###"r#"a "quoted" b"#"###),
+                (Literal, r#"b"\"""#),
+                (Literal, "1.5e3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let tokens: Vec<_> = Lexer::new("a\n  \"é\" b")
+            .map(|t| (t.line, t.column))
+            .collect();
+        assert_eq!(tokens, [(1, 1), (2, 3), (2, 7)]);
+    }
+
+    #[test]
+    fn truncated_input() {
+        use TokenKind::*;
+        assert_eq!(
+            lex("let s = \"abc\\"),
+            [
+                (Ident, "let"),
+                (Ident, "s"),
+                (Punct, "="),
+                (Literal, "\"abc\\"),
+            ]
+        );
+        assert_eq!(lex("'\\"), [(Literal, "'\\")]);
+        assert_eq!(lex("b'\\"), [(Literal, "b'\\")]);
+        assert_eq!(lex("r#"), [(Literal, "r#")]);
+        assert_eq!(lex("r##\"abc\"#"), [(Literal, "r##\"abc\"#")]);
+        assert_eq!(lex("x /* /* */"), [(Ident, "x")]);
+        assert_eq!(lex("\"é\\"), [(Literal, "\"é\\")]);
+    }
+
+    #[test]
+    fn items() {
+        let scan = scan(
+            "pub struct S { field: u8 }
+            impl Trait for S {
+                fn method(&self) {
+                    fn nested() {}
+                }
+            }
+            pub(crate) enum E { A, B(u8) }
+            mod inline {
+                pub const C: u8 = 1;
+            }
+            #[path = \"other.rs\"]
+            pub mod declared;
+            ",
+        );
+        let items: Vec<_> = scan
+            .items
+            .iter()
+            .map(|item| (item.name.as_str(), item.kind, item.scope.join("::")))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                ("S", Kind::Struct, "".to_owned()),
+                ("S", Kind::Impl, "".to_owned()),
+                ("method", Kind::Method, "S".to_owned()),
+                ("nested", Kind::Function, "".to_owned()),
+                ("E", Kind::Enum, "".to_owned()),
+                ("A", Kind::Variant, "E".to_owned()),
+                ("B", Kind::Variant, "E".to_owned()),
+                ("inline", Kind::Module, "".to_owned()),
+                ("C", Kind::Const, "inline".to_owned()),
+                ("declared", Kind::Module, "".to_owned()),
+            ]
+        );
+        assert_eq!(scan.items[1].end_line, 6);
+        assert_eq!(scan.items[4].visibility, Visibility::Crate);
+        assert_eq!(scan.mods.len(), 1);
+        assert_eq!(scan.mods[0].name, "declared");
+        assert_eq!(scan.mods[0].path.as_deref(), Some("other.rs"));
+    }
+
+    #[test]
+    fn truncated_source() {
+        let truncated = scan("fn f() {}\nconst USAGE: &str = \"\\");
+        let names: Vec<_> = truncated
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, ["f", "USAGE"]);
+        scan("macro_rules! m ) mod x; }");
+        scan_all_prefixes("mod m { pub fn f<'a>(x: &'a str) -> char { '\\n' } }");
+    }
+
+    #[test]
+    fn macro_invocations() {
+        let scan = scan(
+            "cfg_if::cfg_if! {
+                if #[cfg(unix)] {
+                    mod inner;
+                    pub use inner::Hidden;
+                } else {
+                    pub mod other { pub fn f() {} }
+                }
+            }
+            fn body() {
+                cfg_if! { mod not_followed; }
+            }",
+        );
+        let mods: Vec<_> = scan.mods.iter().map(|decl| decl.name.as_str()).collect();
+        assert_eq!(mods, ["inner"]);
+        let reexports: Vec<_> = scan.reexports.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(reexports, ["inner::Hidden"]);
+        let f = scan.items.iter().find(|item| item.name == "f").unwrap();
+        assert_eq!(f.scope, ["other"]);
+    }
+
+    #[test]
+    fn visibility_of_lines() {
+        let visibility = declared_visibility;
+        assert_eq!(
+            visibility("const USAGE: &str = \"\\", "USAGE"),
+            Some(Visibility::Private)
+        );
+        assert_eq!(
+            visibility("pub(crate) static mut COUNT: usize = 0;", "COUNT"),
+            Some(Visibility::Crate)
+        );
+        assert_eq!(
+            visibility("pub unsafe extern \"C\" fn f() {", "f"),
+            Some(Visibility::Public)
+        );
+        assert_eq!(
+            visibility("#[macro_export]\n#[doc(hidden)]\nmacro_rules! m {", "m"),
+            Some(Visibility::Public)
+        );
+        // the line of a tag may be the middle of a string
+        assert_eq!(visibility("\" fn f() \\", "f"), None);
+        assert_eq!(visibility("fn g() {", "f"), None);
+        assert!(declares_trait_impl("impl<T> Display for Wrapper<T> {"));
+        assert!(!declares_trait_impl(
+            "impl<T> Wrapper<T> where T: for<'a> Fn(&'a str) {"
+        ));
+    }
+
+    /// Scan every prefix of `source`, as files saved mid-edit are.
+    fn scan_all_prefixes(source: &str) {
+        for (end, _) in source.char_indices() {
+            scan(&source[..end]);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::cli::Config;
+
+/// `path` with `.` and `..` components resolved lexically, without following
+/// symbolic links.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            // `..` stays at the root, and piles up at the start of relative
+            // paths
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::RootDir | Component::Prefix(_)) => (),
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Path of the absolute `path` relative to the absolute directory `base`.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let (path, base) = (normalize(path), normalize(base));
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    relative
+}
+
+/// Rewrites the source paths written to a tags file, as asked for with
+/// `--remap-path-prefix` and `--relative`.
+pub struct PathMap<'c> {
+    remap: &'c [(PathBuf, PathBuf)],
+    /// Directory of the tags file, when paths are made relative to it.
+    base: Option<PathBuf>,
+}
+
+impl<'c> PathMap<'c> {
+    /// Mapping for tags files written to the absolute directory `dir`.
+    pub fn new(config: &'c Config, dir: &Path) -> Self {
+        Self {
+            remap: &config.remap_path_prefix,
+            base: config.relative.then(|| dir.to_owned()),
+        }
+    }
+
+    /// Like rustc, the last prefix mapping matching `path` applies. Paths no
+    /// mapping matches are made relative when asked for.
+    pub fn map(&self, path: &Path) -> PathBuf {
+        for (from, to) in self.remap.iter().rev() {
+            if let Ok(rest) = path.strip_prefix(from) {
+                return match rest.as_os_str().is_empty() {
+                    true => to.clone(),
+                    false => to.join(rest),
+                };
+            }
+        }
+
+        match &self.base {
+            Some(base) => relative_to(path, base),
+            None => path.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized() {
+        assert_eq!(normalize(Path::new("/a/./b/../c/")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("a/../../b")), Path::new("../b"));
+        assert_eq!(normalize(Path::new("./a/b/..")), Path::new("a"));
+        assert_eq!(normalize(Path::new("../../a")), Path::new("../../a"));
+        assert_eq!(normalize(Path::new("/..")), Path::new("/"));
+    }
+
+    #[test]
+    fn relative() {
+        let relative = |path, base| relative_to(Path::new(path), Path::new(base));
+        assert_eq!(relative("/w/src/lib.rs", "/w"), Path::new("src/lib.rs"));
+        assert_eq!(
+            relative(
+                "/home/me/.cargo/registry/serde/src/lib.rs",
+                "/home/me/w/target"
+            ),
+            Path::new("../../.cargo/registry/serde/src/lib.rs")
+        );
+        // components are compared whole, not as strings
+        assert_eq!(relative("/w2/lib.rs", "/w"), Path::new("../w2/lib.rs"));
+        assert_eq!(relative("/w/a/../lib.rs", "/w/./b"), Path::new("../lib.rs"));
+        assert_eq!(relative("/w", "/w"), Path::new(""));
+    }
+
+    #[test]
+    fn remapped() {
+        let config = Config {
+            remap_path_prefix: vec![
+                ("/home/me".into(), "~".into()),
+                ("/home/me/.cargo/registry/src".into(), "$REGISTRY".into()),
+                ("/home/me/w".into(), ".".into()),
+                ("/home".into(), "/users".into()),
+            ],
+            ..Config::default()
+        };
+        let map = PathMap::new(&config, Path::new("/home/me/w"));
+        let mapped = |path| map.map(Path::new(path));
+
+        // the last matching mapping applies, like rustc, so list the
+        // longer prefixes last
+        assert_eq!(
+            mapped("/home/me/w/src/lib.rs"),
+            Path::new("/users/me/w/src/lib.rs")
+        );
+        let config = Config {
+            remap_path_prefix: config.remap_path_prefix[..3].to_vec(),
+            ..Config::default()
+        };
+        let map = PathMap::new(&config, Path::new("/home/me/w"));
+        let mapped = |path| map.map(Path::new(path));
+        assert_eq!(mapped("/home/me/w/src/lib.rs"), Path::new("./src/lib.rs"));
+        assert_eq!(
+            mapped("/home/me/.cargo/registry/src/serde/lib.rs"),
+            Path::new("$REGISTRY/serde/lib.rs")
+        );
+        assert_eq!(mapped("/home/me/notes.rs"), Path::new("~/notes.rs"));
+        assert_eq!(mapped("/home/me"), Path::new("~"));
+        // prefixes match whole components
+        assert_eq!(mapped("/home/mel/lib.rs"), Path::new("/home/mel/lib.rs"));
+    }
+
+    #[test]
+    fn remapped_or_relative() {
+        let config = Config {
+            relative: true,
+            remap_path_prefix: vec![("/rustlib".into(), "/rustc/src".into())],
+            ..Config::default()
+        };
+        let map = PathMap::new(&config, Path::new("/w/target"));
+        assert_eq!(
+            map.map(Path::new("/rustlib/core/lib.rs")),
+            Path::new("/rustc/src/core/lib.rs")
+        );
+        assert_eq!(
+            map.map(Path::new("/w/src/lib.rs")),
+            Path::new("../src/lib.rs")
+        );
+
+        let config = Config::default();
+        let map = PathMap::new(&config, Path::new("/w/target"));
+        assert_eq!(
+            map.map(Path::new("/w/src/lib.rs")),
+            Path::new("/w/src/lib.rs")
+        );
+    }
+}
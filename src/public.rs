@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use crate::{
+    metadata::Tier,
+    native::Exports,
+    tags::{Kind, Tag, Visibility},
+};
+
+/// Whether `--public-only`, as given by `public_only`, filters the tags of
+/// the packages of `tier`. The private items of the workspace members are
+/// still being worked on, so those are always tagged whole.
+pub fn applies(public_only: bool, tier: Tier) -> bool {
+    public_only && tier != Tier::Workspace
+}
+
+/// Keep the tags of the crate `name` that are part of its public API: the
+/// items that can be named from other crates, through public modules from
+/// the crate root or through `pub use` declarations in those.
+///
+/// Members of types and traits are kept when they are public and so is their
+/// type. Impls of types that are not tagged, such as trait impls on types of
+/// other crates, are kept when their module is reachable. Exported macros are
+/// kept whatever their module.
+pub fn retain_reachable(tags: &mut Vec<Tag>, name: &str, exports: &Exports) {
+    let is_public = |tag: &Tag| tag.visibility.is_none_or(|v| v == Visibility::Public);
+    let modules: HashSet<&str> = exports
+        .modules
+        .iter()
+        .map(|(path, _)| path.as_str())
+        .chain([name])
+        .collect();
+
+    // modules whose public items are reachable, and items reachable by
+    // another path, until the reexports in the modules found add no more
+    let mut exported = HashSet::from([name.to_owned()]);
+    let mut reexported = HashSet::new();
+    loop {
+        let found = exported.len() + reexported.len();
+        for (path, visibility) in &exports.modules {
+            let parent = path.rsplit_once("::").map_or("", |(parent, _)| parent);
+            if *visibility == Visibility::Public && exported.contains(parent) {
+                exported.insert(path.clone());
+            }
+        }
+        for reexport in &exports.reexports {
+            if !exported.contains(&reexport.module) {
+                continue;
+            }
+            match reexport.path.strip_suffix("::*") {
+                Some(module) => {
+                    exported.insert(module.to_owned());
+                }
+                // the path may name a module
+                None => {
+                    exported.insert(reexport.path.clone());
+                    reexported.insert(reexport.path.as_str());
+                }
+            }
+        }
+        if exported.len() + reexported.len() == found {
+            break;
+        }
+    }
+
+    // items of modules, as opposed to members of types and traits
+    let items = || {
+        tags.iter()
+            .filter(|tag| tag.kind != Kind::Impl && modules.contains(tag.scope.as_str()))
+    };
+    let defined: HashSet<String> = items().map(Tag::qualified_name).collect();
+    let reachable: HashSet<String> = items()
+        .map(|tag| (tag, tag.qualified_name()))
+        .filter(|(tag, qualified)| {
+            (is_public(tag) && exported.contains(&tag.scope))
+                || reexported.contains(qualified.as_str())
+        })
+        .map(|(_, qualified)| qualified)
+        .collect();
+
+    tags.retain(|tag| match tag.kind {
+        // `#[macro_export]` puts macros at the crate root
+        Kind::Macro => is_public(tag),
+        // impl blocks are named after their self type
+        Kind::Impl => {
+            let self_type = tag.qualified_name();
+            reachable.contains(&self_type)
+                || !defined.contains(&self_type) && exported.contains(&tag.scope)
+        }
+        // modules may share the path of a function, and their items be
+        // reachable through glob imports while they are not
+        Kind::Module => {
+            let qualified = tag.qualified_name();
+            is_public(tag) && exported.contains(&tag.scope)
+                || reexported.contains(qualified.as_str())
+        }
+        _ if modules.contains(tag.scope.as_str()) => reachable.contains(&tag.qualified_name()),
+        _ => {
+            let module = tag.scope.rsplit_once("::").map_or("", |(module, _)| module);
+            is_public(tag)
+                && (reachable.contains(&tag.scope)
+                    || !defined.contains(&tag.scope) && exported.contains(module))
+        }
+    });
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::fnv1a,
+    cli::{Config, Verbosity},
+    metadata::Package,
+    tags::{self, Format, Tag},
+    write_atomic,
+};
+
+/// Index for vim, a script adding the tags files to `'tags'`.
+const VIM_INDEX: &str = "tags.vim";
+/// Index for Emacs, a file setting `tags-table-list`.
+const EMACS_INDEX: &str = "tags.el";
+
+/// Name of the index of the tags files of `format`, for the editor reading
+/// them.
+fn index_name(format: Format) -> &'static str {
+    match format {
+        Format::Etags => EMACS_INDEX,
+        _ => VIM_INDEX,
+    }
+}
+
+/// Name of the tags file of `package` in the split directory, with a hash of
+/// the package id, as packages from different sources may share their name
+/// and version.
+fn file_name(package: &Package, format: Format) -> String {
+    let extension = match format {
+        Format::Ctags => "tags",
+        Format::Etags => "TAGS",
+        Format::Json => "json",
+        Format::Jsonl => "jsonl",
+        Format::Sqlite => "sqlite",
+    };
+    let hash = fnv1a(package.id.as_bytes()) as u32;
+    format!(
+        "{}-{}-{hash:08x}.{extension}",
+        package.name, package.version
+    )
+}
+
+/// `path` as a value of `:set tags+=`.
+fn vim_escape(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace(' ', "\\ ")
+        .replace(',', "\\\\,")
+}
+
+fn vim_unescape(value: &str) -> String {
+    value.replace("\\\\,", ",").replace("\\ ", " ")
+}
+
+/// `path` as an Emacs Lisp string.
+fn elisp_string(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The string of `line` written by `elisp_string`.
+fn elisp_unescape(line: &str) -> Option<String> {
+    let (start, end) = (line.find('"')?, line.rfind('"')?);
+    let mut unescaped = String::new();
+    let mut chars = line.get(start + 1..end)?.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+/// The tags files listed by the index `name` of the split directory `dir`,
+/// in priority order, or `None` when there is no such index.
+fn listed_files(dir: &Path, name: &str) -> Option<Vec<PathBuf>> {
+    let index = fs::read_to_string(dir.join(name)).ok()?;
+    let files: Vec<String> = match name {
+        EMACS_INDEX => index.lines().filter_map(elisp_unescape).collect(),
+        _ => index
+            .lines()
+            .filter_map(|line| line.strip_prefix("set tags+="))
+            .map(vim_unescape)
+            .collect(),
+    };
+    Some(files.into_iter().map(PathBuf::from).collect())
+}
+
+/// The tags files of `format` listed by the index of the split directory
+/// `dir`, in priority order, or `None` when there is no index.
+pub fn indexed_files(dir: &Path, format: Format) -> Option<Vec<PathBuf>> {
+    listed_files(dir, index_name(format))
+}
+
+/// Replace the file at `path` with `contents`, unless it already has them, so
+/// editors and file watchers only see the files that changed.
+fn update(path: &Path, contents: &[u8]) -> io::Result<bool> {
+    if fs::read(path).is_ok_and(|old| old == contents) {
+        return Ok(false);
+    }
+    write_atomic(path, |w| w.write_all(contents))?;
+    Ok(true)
+}
+
+/// Write one tags file per package into `dir`, in priority order, along with
+/// the index listing them for the editors reading the format. Files of
+/// packages no longer tagged are removed, as is the index of the other
+/// format.
+pub fn write(packages: Vec<(&Package, Vec<Tag>)>, dir: &Path, config: &Config) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut files = vec![];
+    for (package, package_tags) in packages {
+        let path = dir.join(file_name(package, config.format));
+        let mut contents = vec![];
+        tags::write(
+            config.format,
+            vec![(package, package_tags)],
+            None,
+            &mut contents,
+        )?;
+        if update(&path, &contents)? && config.verbosity >= Verbosity::Verbose {
+            eprintln!("wrote {}", path.display());
+        }
+        files.push(path);
+    }
+
+    // files listed by the previous indexes that are not anymore, the other
+    // index going with them
+    let current: HashSet<&PathBuf> = files.iter().collect();
+    let index = index_name(config.format);
+    let mut stale = vec![];
+    for name in [VIM_INDEX, EMACS_INDEX] {
+        let listed = listed_files(dir, name).unwrap_or_default();
+        stale.extend(
+            listed
+                .into_iter()
+                .filter(|file| file.parent() == Some(dir) && !current.contains(file)),
+        );
+        if name != index {
+            stale.push(dir.join(name));
+        }
+    }
+    for file in stale {
+        match fs::remove_file(&file) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+    }
+
+    update(&dir.join(index), &write_index(index, &files)?)?;
+    Ok(())
+}
+
+/// Contents of the index `name` listing `files`.
+fn write_index(name: &str, files: &[PathBuf]) -> io::Result<Vec<u8>> {
+    let mut index = vec![];
+    if name == EMACS_INDEX {
+        writeln!(index, ";; generated by cargo-symbols, load with load-file")?;
+        write!(index, "(setq tags-table-list\n      '(")?;
+        for (i, file) in files.iter().enumerate() {
+            if i > 0 {
+                write!(index, "\n        ")?;
+            }
+            write!(index, "{}", elisp_string(file))?;
+        }
+        writeln!(index, "))")?;
+    } else {
+        writeln!(index, "\" generated by cargo-symbols, load with :source")?;
+        for file in files {
+            writeln!(index, "set tags+={}", vim_escape(file))?;
+        }
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(id: &str) -> Package {
+        Package {
+            id: id.to_owned(),
+            name: "dup".to_owned(),
+            version: "1.0.0".to_owned(),
+            ..Package::default()
+        }
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn escapes() {
+        for path in ["/a b/c,d", "/q\"uote\\d/x", "/plain"] {
+            let path = Path::new(path);
+            assert_eq!(vim_unescape(&vim_escape(path)), path.to_string_lossy());
+            let line = format!("      '({}))", elisp_string(path));
+            assert_eq!(elisp_unescape(&line).unwrap(), path.to_string_lossy());
+        }
+    }
+
+    #[test]
+    fn indexes() {
+        let dir = std::env::temp_dir().join(format!("cargo-symbols-split-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (registry, git) = (package("registry+dup@1.0.0"), package("git+dup@1.0.0"));
+        let write = |format, packages: &[&Package]| {
+            let config = Config {
+                format,
+                split: true,
+                ..Config::default()
+            };
+            let packages = packages.iter().map(|&package| (package, vec![])).collect();
+            write(packages, &dir, &config).unwrap();
+        };
+
+        // packages of the same name and version get files of their own
+        write(Format::Etags, &[&registry, &git]);
+        let etags = [
+            dir.join(file_name(&registry, Format::Etags)),
+            dir.join(file_name(&git, Format::Etags)),
+        ];
+        assert_ne!(etags[0], etags[1]);
+        assert_eq!(names(&dir).len(), 3);
+        assert_eq!(indexed_files(&dir, Format::Etags).unwrap(), etags);
+        assert!(indexed_files(&dir, Format::Ctags).is_none());
+
+        // the files and index of the other format are removed
+        write(Format::Ctags, &[&git]);
+        let ctags = dir.join(file_name(&git, Format::Ctags));
+        assert_eq!(
+            indexed_files(&dir, Format::Ctags).unwrap(),
+            vec![ctags.clone()]
+        );
+        let ctags_name = ctags.file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(names(&dir), [ctags_name, VIM_INDEX.to_owned()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
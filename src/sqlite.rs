@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+    process::Command,
+};
+
+use crate::{
+    cli::Config,
+    metadata::Package,
+    replace_file, run,
+    tags::{Kind, Tag},
+    AnyError,
+};
+
+/// Tables of the database: packages, their source files, the symbols in
+/// those and the impl blocks among the symbols.
+const SCHEMA: &str = "\
+CREATE TABLE crates (
+    id INTEGER PRIMARY KEY,
+    package_id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    source TEXT,
+    source_kind TEXT NOT NULL,
+    tier TEXT NOT NULL
+);
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    crate_id INTEGER NOT NULL REFERENCES crates (id),
+    path TEXT NOT NULL
+);
+CREATE TABLE symbols (
+    id INTEGER PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files (id),
+    name TEXT NOT NULL,
+    qualified_name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    column INTEGER NOT NULL,
+    end_line INTEGER NOT NULL,
+    visibility TEXT,
+    -- the impl block of methods and associated items
+    impl_id INTEGER REFERENCES impls (id)
+);
+CREATE TABLE impls (
+    id INTEGER PRIMARY KEY,
+    symbol_id INTEGER NOT NULL REFERENCES symbols (id),
+    self_type TEXT NOT NULL
+);
+CREATE INDEX symbols_name ON symbols (name);
+CREATE INDEX symbols_qualified_name ON symbols (qualified_name);
+";
+
+/// `s` as an SQL string literal.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Write the SQL statements creating the database of the tags of `packages`.
+pub fn write_script(packages: &[(&Package, Vec<Tag>)], w: &mut impl Write) -> io::Result<()> {
+    writeln!(w, "BEGIN;")?;
+    w.write_all(SCHEMA.as_bytes())?;
+
+    let (mut file_id, mut symbol_id, mut impl_id) = (0, 0, 0);
+    for (crate_id, (package, tags)) in packages.iter().enumerate() {
+        let crate_id = crate_id + 1;
+        writeln!(
+            w,
+            "INSERT INTO crates VALUES ({crate_id}, {}, {}, {}, {}, {}, {});",
+            quote(&package.id),
+            quote(&package.name),
+            quote(&package.version),
+            package.source.as_deref().map_or("NULL".to_owned(), quote),
+            quote(package.source_kind().name()),
+            quote(package.tier.name()),
+        )?;
+
+        let mut files = HashMap::new();
+        for tag in tags {
+            if !files.contains_key(tag.file.as_path()) {
+                file_id += 1;
+                files.insert(tag.file.as_path(), file_id);
+                let path = quote(&tag.file.to_string_lossy());
+                writeln!(
+                    w,
+                    "INSERT INTO files VALUES ({file_id}, {crate_id}, {path});"
+                )?;
+            }
+        }
+
+        // symbols are numbered in order, so those of the impl blocks are known
+        // before the items within are written
+        let mut impls: HashMap<(&Path, String), Vec<(usize, &Tag)>> = HashMap::new();
+        let mut impl_rows = vec![];
+        for (i, tag) in tags.iter().enumerate() {
+            if tag.kind == Kind::Impl {
+                impl_id += 1;
+                let key = (tag.file.as_path(), tag.qualified_name());
+                impls.entry(key).or_default().push((impl_id, tag));
+                impl_rows.push((impl_id, symbol_id + i + 1, tag.qualified_name()));
+            }
+        }
+
+        for tag in tags {
+            symbol_id += 1;
+            // associated items are in a block implementing their scope
+            let within = impls
+                .get(&(tag.file.as_path(), tag.scope.clone()))
+                .filter(|_| tag.kind != Kind::Impl)
+                .and_then(|impls| {
+                    impls
+                        .iter()
+                        .find(|(_, block)| block.line <= tag.line && tag.line <= block.end_line)
+                })
+                .map_or("NULL".to_owned(), |(id, _)| id.to_string());
+            writeln!(
+                w,
+                "INSERT INTO symbols VALUES ({symbol_id}, {}, {}, {}, {}, {}, {}, {}, {}, {within});",
+                files[tag.file.as_path()],
+                quote(&tag.name),
+                quote(&tag.qualified_name()),
+                quote(tag.kind.name()),
+                tag.line,
+                tag.column,
+                tag.end_line,
+                tag.visibility.map_or("NULL".to_owned(), |v| quote(v.name())),
+            )?;
+        }
+
+        for (id, symbol, self_type) in impl_rows {
+            let self_type = quote(&self_type);
+            writeln!(w, "INSERT INTO impls VALUES ({id}, {symbol}, {self_type});")?;
+        }
+    }
+
+    writeln!(w, "COMMIT;")
+}
+
+/// Create the database of the tags of `packages` at `output` with the
+/// `sqlite3` shell, through a temporary sibling renamed over it as for tags
+/// files.
+pub fn write(
+    packages: &[(&Package, Vec<Tag>)],
+    output: &Path,
+    config: &Config,
+) -> Result<(), AnyError> {
+    let mut script = vec![];
+    write_script(packages, &mut script)?;
+
+    replace_file(output, |temp| {
+        // sqlite3 would add to a database left over by an earlier run
+        let _ = fs::remove_file(temp);
+
+        let mut command = Command::new(&config.sqlite3);
+        command.arg("-bail").arg(temp);
+        run(command, script, config).map(drop)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{metadata::Tier, tags::Visibility};
+
+    fn tag(name: &str, scope: &str, kind: Kind, lines: (usize, usize)) -> Tag {
+        Tag {
+            name: name.to_owned(),
+            file: PathBuf::from("/src/it's.rs"),
+            line: lines.0,
+            column: 1,
+            end_line: lines.1,
+            offset: 0,
+            kind,
+            pattern: String::new(),
+            scope: scope.to_owned(),
+            visibility: Some(Visibility::Public),
+        }
+    }
+
+    #[test]
+    fn script() {
+        let package = Package {
+            id: "o'brien 0.1.0 (path+file:///src)".to_owned(),
+            name: "o'brien".to_owned(),
+            version: "0.1.0".to_owned(),
+            tier: Tier::Workspace,
+            ..Package::default()
+        };
+        let tags = vec![
+            tag("Point", "k", Kind::Struct, (1, 1)),
+            tag("Point", "k", Kind::Impl, (3, 5)),
+            tag("new", "k::Point", Kind::Method, (4, 4)),
+            tag("Point", "k", Kind::Impl, (7, 9)),
+            tag("norm", "k::Point", Kind::Method, (8, 8)),
+            tag("x", "k::Point", Kind::Field, (1, 1)),
+        ];
+        let mut script = vec![];
+        write_script(&[(&package, tags)], &mut script).unwrap();
+        let script = String::from_utf8(script).unwrap();
+
+        let inserts: Vec<_> = script
+            .lines()
+            .filter(|line| line.starts_with("INSERT"))
+            .collect();
+        assert_eq!(
+            inserts,
+            [
+                "INSERT INTO crates VALUES (1, 'o''brien 0.1.0 (path+file:///src)', 'o''brien', \
+                 '0.1.0', NULL, 'path', 'workspace');",
+                "INSERT INTO files VALUES (1, 1, '/src/it''s.rs');",
+                "INSERT INTO symbols VALUES (1, 1, 'Point', 'k::Point', 'struct', 1, 1, 1, 'pub', NULL);",
+                "INSERT INTO symbols VALUES (2, 1, 'Point', 'k::Point', 'impl', 3, 1, 5, 'pub', NULL);",
+                "INSERT INTO symbols VALUES (3, 1, 'new', 'k::Point::new', 'method', 4, 1, 4, 'pub', 1);",
+                "INSERT INTO symbols VALUES (4, 1, 'Point', 'k::Point', 'impl', 7, 1, 9, 'pub', NULL);",
+                "INSERT INTO symbols VALUES (5, 1, 'norm', 'k::Point::norm', 'method', 8, 1, 8, 'pub', 2);",
+                "INSERT INTO symbols VALUES (6, 1, 'x', 'k::Point::x', 'field', 1, 1, 1, 'pub', NULL);",
+                "INSERT INTO impls VALUES (1, 2, 'k::Point');",
+                "INSERT INTO impls VALUES (2, 4, 'k::Point');",
+            ]
+        );
+        assert!(script.starts_with("BEGIN;\nCREATE TABLE crates"));
+        assert!(script.ends_with("COMMIT;\n"));
+    }
+
+    #[test]
+    fn ids_across_packages() {
+        let packages = [Package::default(), Package::default()];
+        let tags = || {
+            vec![
+                tag("T", "k", Kind::Impl, (1, 3)),
+                tag("f", "k::T", Kind::Method, (2, 2)),
+            ]
+        };
+        let mut script = vec![];
+        write_script(
+            &[(&packages[0], tags()), (&packages[1], tags())],
+            &mut script,
+        )
+        .unwrap();
+        let script = String::from_utf8(script).unwrap();
+
+        assert!(script.contains("INSERT INTO files VALUES (2, 2, '/src/it''s.rs');"));
+        assert!(script.contains(
+            "INSERT INTO symbols VALUES (4, 2, 'f', 'k::T::f', 'method', 2, 1, 2, 'pub', 2);"
+        ));
+        assert!(script.contains("INSERT INTO impls VALUES (2, 3, 'k::T');"));
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    json::{self, Json},
+    metadata::Package,
+};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Format {
+    /// vi style `tags`.
+    Ctags,
+    /// Emacs style `TAGS`.
+    Etags,
+    /// A JSON array of symbol records.
+    Json,
+    /// One JSON symbol record per line.
+    Jsonl,
+    /// An SQLite database.
+    Sqlite,
+}
+
+impl Format {
+    pub fn default_file_name(self) -> &'static str {
+        match self {
+            Format::Ctags => "tags",
+            Format::Etags => "TAGS",
+            Format::Json => "tags.json",
+            Format::Jsonl => "tags.jsonl",
+            Format::Sqlite => "tags.sqlite",
+        }
+    }
+
+    /// Whether the format is one of the tags files editors read.
+    pub fn is_tags_file(self) -> bool {
+        matches!(self, Format::Ctags | Format::Etags)
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Kind {
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Variant,
+    Trait,
+    Impl,
+    Type,
+    Const,
+    Static,
+    Module,
+    Macro,
+    Union,
+    Field,
+}
+
+impl Kind {
+    /// Single letter kind, matching the universal-ctags Rust parser.
+    pub fn letter(self) -> char {
+        use Kind::*;
+        match self {
+            Function => 'f',
+            Method => 'P',
+            Struct => 's',
+            Enum => 'g',
+            Variant => 'e',
+            Trait => 'i',
+            Impl => 'c',
+            Type => 't',
+            Const => 'C',
+            Static => 'v',
+            Module => 'n',
+            Macro => 'M',
+            Union => 'u',
+            Field => 'm',
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        use Kind::*;
+        match self {
+            Function => "function",
+            Method => "method",
+            Struct => "struct",
+            Enum => "enum",
+            Variant => "variant",
+            Trait => "trait",
+            Impl => "impl",
+            Type => "type",
+            Const => "const",
+            Static => "static",
+            Module => "module",
+            Macro => "macro",
+            Union => "union",
+            Field => "field",
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Self> {
+        use Kind::*;
+        [
+            Function, Method, Struct, Enum, Variant, Trait, Impl, Type, Const, Static, Module,
+            Macro, Union, Field,
+        ]
+        .into_iter()
+        .find(|kind| kind.letter() == letter)
+    }
+}
+
+/// Visibility of an item, as written in the source.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Visibility {
+    /// `pub`
+    Public,
+    /// `pub(crate)`
+    Crate,
+    /// `pub(super)`
+    Super,
+    /// `pub(in path)`
+    Restricted,
+    /// No visibility, or `pub(self)`.
+    Private,
+}
+
+impl Visibility {
+    pub const ALL: [Visibility; 5] = [
+        Visibility::Public,
+        Visibility::Crate,
+        Visibility::Super,
+        Visibility::Restricted,
+        Visibility::Private,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Visibility::Public => "pub",
+            Visibility::Crate => "pub(crate)",
+            Visibility::Super => "pub(super)",
+            Visibility::Restricted => "pub(in)",
+            Visibility::Private => "private",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.name() == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub name: String,
+    pub file: PathBuf,
+    pub line: usize,
+    /// Column of the name on its line, in characters from 1.
+    pub column: usize,
+    /// Last line of the item.
+    pub end_line: usize,
+    /// Byte offset of the start of the line.
+    pub offset: usize,
+    pub kind: Kind,
+    /// The source line the tag is found on.
+    pub pattern: String,
+    /// Path of the module, type or trait the item is defined in, starting
+    /// with the crate name. Empty when the name is already the full path.
+    pub scope: String,
+    /// Unknown when the backend does not tell.
+    pub visibility: Option<Visibility>,
+}
+
+impl Tag {
+    /// Fully qualified path of the item, such as `serde::de::Error`.
+    pub fn qualified_name(&self) -> String {
+        match self.scope.is_empty() {
+            true => self.name.clone(),
+            false => format!("{}::{}", self.scope, self.name),
+        }
+    }
+}
+
+/// Write `tags`, with the packages they come from, in the extended vi tags
+/// format, sorted by name. Entries of the same name keep their order in
+/// `tags`, which editors take as priority.
+///
+/// Entries of an `existing` tags file are kept, unless they point into a file
+/// that is tagged again.
+pub fn write_ctags(
+    tags: &mut [(&Package, Tag)],
+    existing: Option<&str>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    tags.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+
+    let mut lines: Vec<String> = tags
+        .iter()
+        .map(|(package, tag)| {
+            let pattern = tag.pattern.replace('\\', "\\\\").replace('/', "\\/");
+            let visibility = tag
+                .visibility
+                .map_or(String::new(), |v| format!("\tvisibility:{}", v.name()));
+            format!(
+                "{}\t{}\t/^{pattern}$/;\"\t{}\tline:{}\tcolumn:{}\tqualified:{}{visibility}\tcrate:{}@{}",
+                tag.name,
+                tag.file.display(),
+                tag.kind.letter(),
+                tag.line,
+                tag.column,
+                tag.qualified_name(),
+                package.name,
+                package.version,
+            )
+        })
+        .collect();
+
+    if let Some(existing) = existing {
+        let files: HashSet<&Path> = tags.iter().map(|(_, tag)| tag.file.as_path()).collect();
+        let kept = existing.lines().filter(|line| {
+            let file = line.split('\t').nth(1);
+            !line.starts_with("!_TAG_") && file.is_some_and(|file| !files.contains(Path::new(file)))
+        });
+        lines.extend(kept.map(str::to_owned));
+        // stable, so the entries of one name keep their order
+        lines.sort_by(|a, b| a.split('\t').next().cmp(&b.split('\t').next()));
+    }
+
+    writeln!(w, "!_TAG_FILE_FORMAT\t2\t/extended format/")?;
+    writeln!(
+        w,
+        "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/"
+    )?;
+    writeln!(w, "!_TAG_PROGRAM_NAME\tcargo-symbols\t//")?;
+    writeln!(
+        w,
+        "!_TAG_PROGRAM_VERSION\t{}\t//",
+        env!("CARGO_PKG_VERSION")
+    )?;
+
+    for line in lines {
+        writeln!(w, "{line}")?;
+    }
+
+    Ok(())
+}
+
+/// Write `tags` in the Emacs etags format, one section per source file.
+///
+/// Sections of an `existing` tags file are kept, unless their file is tagged
+/// again.
+pub fn write_etags(tags: &mut [Tag], existing: Option<&str>, w: &mut impl Write) -> io::Result<()> {
+    // sections follow the order files were tagged in
+    let mut order: HashMap<PathBuf, usize> = HashMap::new();
+    for tag in tags.iter() {
+        let next = order.len();
+        order.entry(tag.file.clone()).or_insert(next);
+    }
+    tags.sort_by_key(|tag| (order[&tag.file], tag.line));
+
+    for section in existing.iter().flat_map(|existing| existing.split('\x0c')) {
+        let header = section.trim_start_matches('\n').lines().next();
+        let file = header
+            .and_then(|header| header.rsplit_once(','))
+            .map(|(file, _)| file);
+        if file.is_some_and(|file| !order.contains_key(Path::new(file))) {
+            write!(w, "\x0c{section}")?;
+        }
+    }
+
+    for section in tags.chunk_by(|a, b| a.file == b.file) {
+        let mut entries = vec![];
+        for tag in section {
+            // the line up to the end of the tag name, at its column
+            let start = tag.pattern.char_indices().nth(tag.column.saturating_sub(1));
+            let pattern = match start {
+                Some((start, _)) if tag.pattern[start..].starts_with(&tag.name) => {
+                    &tag.pattern[..start + tag.name.len()]
+                }
+                _ => &tag.pattern,
+            };
+            writeln!(
+                entries,
+                "{pattern}\x7f{}\x01{},{}",
+                tag.name, tag.line, tag.offset
+            )?;
+        }
+
+        write!(w, "\x0c\n{},{}\n", section[0].file.display(), entries.len())?;
+        w.write_all(&entries)?;
+    }
+
+    Ok(())
+}
+
+/// The JSON record of a tag of `package`.
+fn json_record(tag: &Tag, package: &Package) -> Json {
+    let string = |s: &str| Json::Str(s.to_owned());
+    let number = |n: usize| Json::Number(n as f64);
+    Json::Obj(vec![
+        ("name".to_owned(), string(&tag.name)),
+        ("kind".to_owned(), string(tag.kind.name())),
+        ("qualified_name".to_owned(), string(&tag.qualified_name())),
+        ("file".to_owned(), string(&tag.file.to_string_lossy())),
+        ("line".to_owned(), number(tag.line)),
+        ("column".to_owned(), number(tag.column)),
+        ("end_line".to_owned(), number(tag.end_line)),
+        (
+            "visibility".to_owned(),
+            tag.visibility.map_or(Json::Null, |v| string(v.name())),
+        ),
+        ("crate_name".to_owned(), string(&package.name)),
+        ("crate_version".to_owned(), string(&package.version)),
+        ("package_id".to_owned(), string(&package.id)),
+    ])
+}
+
+/// Write one JSON record per tag of `packages`, as a JSON array or, with
+/// `lines`, one record per line.
+///
+/// Records of an `existing` file are kept, unless they point into a file that
+/// is tagged again.
+pub fn write_json(
+    packages: &[(&Package, Vec<Tag>)],
+    lines: bool,
+    existing: Option<&str>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let mut records: Vec<Json> = packages
+        .iter()
+        .flat_map(|(package, tags)| tags.iter().map(|tag| json_record(tag, package)))
+        .collect();
+
+    if let Some(existing) = existing {
+        let files: HashSet<String> = packages
+            .iter()
+            .flat_map(|(_, tags)| tags)
+            .map(|tag| tag.file.to_string_lossy().into_owned())
+            .collect();
+        let old = match lines {
+            true => existing
+                .lines()
+                .filter_map(|line| json::parse(line).ok())
+                .collect(),
+            false => json::parse(existing).map_or(vec![], |list| list.items().to_vec()),
+        };
+        let kept = old.into_iter().filter(|record| {
+            let file = record.get("file").and_then(Json::as_str);
+            file.is_some_and(|file| !files.contains(file))
+        });
+        records.extend(kept);
+    }
+
+    if lines {
+        for record in records {
+            writeln!(w, "{record}")?;
+        }
+        return Ok(());
+    }
+
+    write!(w, "[")?;
+    for (i, record) in records.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        write!(w, "{separator}\n{record}")?;
+    }
+    writeln!(w, "\n]")
+}
+
+/// Write the tags of `packages`, given in priority order.
+pub fn write(
+    format: Format,
+    packages: Vec<(&Package, Vec<Tag>)>,
+    existing: Option<&str>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    match format {
+        Format::Json | Format::Jsonl => {
+            return write_json(&packages, format == Format::Jsonl, existing, w);
+        }
+        Format::Sqlite => unreachable!("databases are created by `sqlite::write`"),
+        Format::Ctags | Format::Etags => (),
+    }
+
+    if format == Format::Etags {
+        let mut tags: Vec<Tag> = packages.into_iter().flat_map(|(_, tags)| tags).collect();
+        return write_etags(&mut tags, existing, w);
+    }
+
+    let mut tags: Vec<_> = packages
+        .into_iter()
+        .flat_map(|(package, tags)| tags.into_iter().map(move |tag| (package, tag)))
+        .collect();
+    write_ctags(&mut tags, existing, w)
+}
+
+/// Byte offset and text of every line in `source`.
+pub fn line_offsets(source: &str) -> Vec<(usize, String)> {
+    let mut offset = 0;
+    source
+        .split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, line.trim_end_matches(['\n', '\r']).to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, file: &str, line: usize, column: usize, pattern: &str) -> Tag {
+        Tag {
+            name: name.to_owned(),
+            file: PathBuf::from(file),
+            line,
+            column,
+            end_line: line,
+            offset: (line - 1) * 20,
+            kind: Kind::Function,
+            pattern: pattern.to_owned(),
+            scope: "krate".to_owned(),
+            visibility: None,
+        }
+    }
+
+    fn etags(tags: &mut [Tag], existing: Option<&str>) -> String {
+        let mut w = vec![];
+        write_etags(tags, existing, &mut w).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    #[test]
+    fn etags_entries() {
+        let mut tags = [
+            tag("f", "src/lib.rs", 1, 8, "pub fn f() {}"),
+            tag("n", "src/lib.rs", 2, 8, "pub fn n() {}"),
+            tag("m", "src/lib.rs", 3, 14, "macro_rules! m {"),
+            tag("é", "src/é.rs", 1, 8, "pub fn é() {}"),
+            tag("krate::f", "src/lib.rs", 1, 8, "pub fn f() {}"),
+        ];
+        assert_eq!(
+            etags(&mut tags, None),
+            "\x0c\nsrc/lib.rs,80\n\
+             pub fn f\x7ff\x011,0\n\
+             pub fn f() {}\x7fkrate::f\x011,0\n\
+             pub fn n\x7fn\x012,20\n\
+             macro_rules! m\x7fm\x013,40\n\
+             \x0c\nsrc/é.rs,17\n\
+             pub fn é\x7fé\x011,0\n"
+        );
+    }
+
+    #[test]
+    fn etags_section_sizes() {
+        let mut tags = [
+            tag("a", "a.rs", 1, 4, "fn a() {}"),
+            tag("b", "a.rs", 2, 4, "fn b() {}"),
+        ];
+        let written = etags(&mut tags, None);
+        let section = written.strip_prefix("\x0c\n").unwrap();
+        let (header, entries) = section.split_once('\n').unwrap();
+        let size: usize = header.rsplit_once(',').unwrap().1.parse().unwrap();
+        assert_eq!(size, entries.len());
+    }
+
+    #[test]
+    fn etags_append() {
+        let existing = etags(
+            &mut [
+                tag("old", "a.rs", 1, 4, "fn old() {}"),
+                tag("kept", "b.rs", 1, 4, "fn kept() {}"),
+            ],
+            None,
+        );
+        let written = etags(
+            &mut [tag("new", "a.rs", 1, 4, "fn new() {}")],
+            Some(&existing),
+        );
+        assert_eq!(
+            written,
+            "\x0c\nb.rs,17\nfn kept\x7fkept\x011,0\n\
+             \x0c\na.rs,15\nfn new\x7fnew\x011,0\n"
+        );
+    }
+
+    #[test]
+    fn ctags_keep_priority() {
+        // given in priority order, as the packages are ranked by tier
+        let packages: Vec<_> = ["app", "serde", "std"]
+            .into_iter()
+            .map(|name| Package {
+                name: name.to_owned(),
+                version: "1.0.0".to_owned(),
+                ..Package::default()
+            })
+            .collect();
+        let mut tags = vec![
+            (&packages[0], tag("new", "app.rs", 1, 8, "pub fn new() {}")),
+            (&packages[0], tag("run", "app.rs", 2, 8, "pub fn run() {}")),
+            (
+                &packages[1],
+                tag("new", "serde.rs", 1, 8, "pub fn new() {}"),
+            ),
+            (&packages[1], tag("de", "serde.rs", 2, 8, "pub fn de() {}")),
+            (&packages[2], tag("new", "std.rs", 1, 8, "pub fn new() {}")),
+            (
+                &packages[2],
+                tag("alloc", "std.rs", 2, 8, "pub fn alloc() {}"),
+            ),
+        ];
+        let existing = "new\told.rs\t/^fn new() {}$/;\"\tf\tline:1\n";
+        let mut w = vec![];
+        write_ctags(&mut tags, Some(existing), &mut w).unwrap();
+        let entries: Vec<_> = String::from_utf8(w)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with("!_TAG_"))
+            .map(|line| {
+                let mut fields = line.split('\t');
+                (
+                    fields.next().unwrap().to_owned(),
+                    fields.next().unwrap().to_owned(),
+                )
+            })
+            .collect();
+        let expected = [
+            ("alloc", "std.rs"),
+            ("de", "serde.rs"),
+            ("new", "app.rs"),
+            ("new", "serde.rs"),
+            ("new", "std.rs"),
+            ("new", "old.rs"),
+            ("run", "app.rs"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(name, file)| (name.to_owned(), file.to_owned()))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+}